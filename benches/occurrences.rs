@@ -0,0 +1,61 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use when_rs::{date, Calendar, Easter};
+
+// calendar_text returns a calendar with lines lines, in the mix of a large
+// shared calendar: mostly fixed dates, some weekly and monthly items and
+// some expressions.
+fn calendar_text(lines: usize) -> String {
+    let months = ["jan", "feb", "mar", "apr", "may", "jun",
+                  "jul", "aug", "sep", "oct", "nov", "dec"];
+    (0..lines)
+        .map(|i| {
+            let month = months[i % 12];
+            let day = i % 28 + 1;
+            let year = 2020 + i % 10;
+            match i % 10 {
+                0..=3 => format!("* {} {}, Birthday {}", month, day, i),
+                4..=5 => format!("{} {} {}, Appointment {}", year, month, day, i),
+                6 => format!("w={} & a={}, Meeting {}", i % 5 + 1, i % 4 + 1, i),
+                7 => format!("m={} & d>={} & d<={}, Week {}", i % 12 + 1, day, day + 6, i),
+                8 => format!("z={}, Day of year {}", i % 365 + 1, i),
+                _ => format!("y={} & m={} & d={}, Deadline {}", year, month, day, i),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn occurrences(c: &mut Criterion) {
+    let calendar = Calendar::from_string("bench", &calendar_text(5000), Easter::Western);
+    let first = date::new_date_opt(2024, 1, 1).unwrap();
+    let last = date::new_date_opt(2033, 12, 31).unwrap();
+    let mut group = c.benchmark_group("5000 lines, 10 years");
+    group.sample_size(10);
+    group.bench_function("dates, planned", |b| b.iter(|| {
+        calendar.entries.iter()
+            .map(|entry| entry.checker.occurrences(&first).until(last).count())
+            .sum::<usize>()
+    }));
+    // Checking every day of the range, as when-rs did before the planner.
+    group.bench_function("dates, scanned", |b| b.iter(|| {
+        calendar.entries.iter()
+            .map(|entry| {
+                let mut d = first;
+                let mut n = 0;
+                while d <= last {
+                    n += entry.checker.check_date(&d) as usize;
+                    d = d.succ_opt().unwrap();
+                }
+                n
+            })
+            .sum::<usize>()
+    }));
+    // Sorted occurrences with descriptions, as in the report.
+    group.bench_function("calendar occurrences", |b| {
+        b.iter(|| calendar.occurrences(&first, &last))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, occurrences);
+criterion_main!(benches);
@@ -0,0 +1,242 @@
+use crate::date::{self, TimeRange};
+use crate::datecalc::{self, DateChecker, Dates, Occurrence};
+use crate::diagnostic::Diagnostic;
+use crate::easter::Easter;
+use crate::error::Error;
+use crate::include;
+use crate::utils;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::Path;
+
+// Item of a calendar, from a line like "w=1, 09:30-10:00, Standup".
+#[derive(PartialEq, Debug, Clone)]
+pub struct Entry {
+    pub file: String, // name of the calendar file, which may be an included one
+    pub line_number: usize, // starting with 1
+    pub line: String,
+    pub checker: DateChecker,
+    pub time: Option<TimeRange>,
+    pub description: String,
+}
+
+impl Entry {
+    // occurrences returns occurrences of the entry between first and last,
+    // inclusive.
+    pub fn occurrences(&self, first: &date::Date, last: &date::Date) -> Vec<Occurrence> {
+        self.checker.check_date_range(first, last).into_iter()
+            .map(|date| self.occurrence(date))
+            .collect()
+    }
+
+    // next_occurrence returns the first occurrence of the entry from date
+    // from to until, inclusive.
+    pub fn next_occurrence(&self, from: &date::Date, until: &date::Date) -> Option<Occurrence> {
+        self.checker.occurrences(from).until(*until).next().map(|date| self.occurrence(date))
+    }
+
+    fn occurrence(&self, date: date::Date) -> Occurrence {
+        Occurrence {
+            date,
+            time: self.time,
+            description: self.description.clone(),
+            span_day: self.checker.span_day(&date),
+        }
+    }
+}
+
+// Iterator over occurrences of all entries of a calendar in the order of
+// date and time, see Calendar::upcoming.
+pub struct Upcoming<'a> {
+    entries: &'a [Entry],
+    dates: Vec<Dates<'a>>, // of each entry
+    // Next date of each entry that has one, the first one on top.
+    heap: BinaryHeap<Reverse<(date::Date, Option<TimeRange>, usize)>>,
+}
+
+impl<'a> Upcoming<'a> {
+    fn push_next(&mut self, index: usize) {
+        if let Some(date) = self.dates[index].next() {
+            self.heap.push(Reverse((date, self.entries[index].time, index)));
+        }
+    }
+}
+
+impl<'a> Iterator for Upcoming<'a> {
+    type Item = Occurrence;
+
+    fn next(&mut self) -> Option<Occurrence> {
+        let Reverse((date, _, index)) = self.heap.pop()?;
+        self.push_next(index);
+        Some(self.entries[index].occurrence(date))
+    }
+}
+
+// Calendar file, parsed into entries. Lines that can't be parsed are kept
+// as diagnostics; it is up to the caller to report them.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Calendar {
+    pub name: String, // file name, used in diagnostics
+    pub entries: Vec<Entry>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Calendar {
+    // from_path reads and parses calendar file at path, and the files that
+    // it includes with #include lines.
+    pub fn from_path(path: &Path, easter: Easter) -> Result<Calendar, Error> {
+        let (files, diagnostics) = include::read_files(path)?;
+        let mut calendar = Calendar {
+            name: path.display().to_string(),
+            entries: Vec::new(),
+            diagnostics,
+        };
+        for file in &files {
+            calendar.add_text(&file.name, &file.text, easter);
+        }
+        Ok(calendar)
+    }
+
+    // from_string parses calendar text. The name is used in diagnostics.
+    // Include lines are treated as comments, since there is no file that
+    // they would be relative to.
+    pub fn from_string(name: &str, text: &str, easter: Easter) -> Calendar {
+        let mut calendar = Calendar {
+            name: name.to_string(),
+            entries: Vec::new(),
+            diagnostics: Vec::new(),
+        };
+        calendar.add_text(name, text, easter);
+        calendar
+    }
+
+    // add_text parses text of the calendar file with the name and adds its
+    // entries and problems to the calendar.
+    fn add_text(&mut self, name: &str, text: &str, easter: Easter) {
+        for (index, line) in text.lines().enumerate() {
+            match utils::parse_calendar_entry(line, easter) {
+                Ok(Some((checker, time, description))) => {
+                    self.entries.push(Entry {
+                        file: name.to_string(),
+                        line_number: index + 1,
+                        line: line.to_string(),
+                        checker,
+                        time,
+                        description,
+                    });
+                },
+                Ok(None) => (),
+                Err(error) => {
+                    self.diagnostics.push(Diagnostic::new(
+                        name, index + 1, line, error.span, &error.message));
+                },
+            }
+        }
+    }
+
+    // upcoming returns occurrences of all entries from date from to until,
+    // inclusive, in the order of date and time. It finds them as they are
+    // needed, so calendar.upcoming(&today, &until).take(5) are the next
+    // five, however far apart they are.
+    pub fn upcoming(&self, from: &date::Date, until: &date::Date) -> Upcoming<'_> {
+        let mut upcoming = Upcoming {
+            entries: &self.entries,
+            dates: self.entries.iter()
+                .map(|entry| entry.checker.occurrences(from).until(*until))
+                .collect(),
+            heap: BinaryHeap::new(),
+        };
+        for index in 0..self.entries.len() {
+            upcoming.push_next(index);
+        }
+        upcoming
+    }
+
+    // occurrences returns occurrences of all entries between first and
+    // last, inclusive, sorted by date and time.
+    pub fn occurrences(&self, first: &date::Date, last: &date::Date) -> Vec<Occurrence> {
+        let occurrences = self.entries.iter()
+            .flat_map(|entry| entry.occurrences(first, last))
+            .collect();
+        datecalc::sort_dates(occurrences)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+
+    #[test]
+    fn calendar_test() {
+        let text = "# Test\n\
+                    w=mon, 09:30, Standup\n\
+                    * Jly 4, Bad month\n\
+                    2021 Sep 26 +2d, Trip\n";
+        let calendar = Calendar::from_string("test", text, Easter::Western);
+        assert_eq!(calendar.entries.len(), 2);
+        assert_eq!(calendar.entries[1].line_number, 4);
+        assert_eq!(calendar.diagnostics.len(), 1);
+        assert_eq!(calendar.diagnostics[0].line_number, 3);
+        assert_eq!(calendar.diagnostics[0].message, "Bad month");
+
+        let occurrences = calendar.occurrences(&date::new_date(2021, 9, 26),
+                                               &date::new_date(2021, 9, 28));
+        let summary: Vec<_> = occurrences.iter()
+            .map(|o| (o.date.day(), &o.description[..], o.span_day))
+            .collect();
+        assert_eq!(summary, vec![
+            (26, "Trip", Some((1, 2))),
+            (27, "Trip", Some((2, 2))),
+            (27, "Standup", None),
+        ]);
+        assert!(occurrences[2].time.is_some());
+    }
+
+    #[test]
+    fn upcoming_test() {
+        let text = "2024 Jun 5, 14:00, Review\n\
+                    w=wed, 09:30, Standup\n\
+                    * Dec 25, Christmas\n\
+                    y=2020 & m=jan & d=1, Past\n\
+                    2024 Jun 5, Deadline\n";
+        let calendar = Calendar::from_string("test", text, Easter::Western);
+        let from = date::new_date(2024, 6, 1);
+        let until = date::new_date(2034, 6, 1);
+        let next: Vec<_> = calendar.upcoming(&from, &until).take(4)
+            .map(|o| (o.date, o.description))
+            .collect();
+        assert_eq!(next, vec![
+            (date::new_date(2024, 6, 5), "Deadline".to_string()),
+            (date::new_date(2024, 6, 5), "Standup".to_string()),
+            (date::new_date(2024, 6, 5), "Review".to_string()),
+            (date::new_date(2024, 6, 12), "Standup".to_string()),
+        ]);
+        assert_eq!(calendar.upcoming(&from, &until)
+                       .filter(|o| o.description == "Christmas").count(), 10);
+
+        assert_eq!(calendar.entries[2].next_occurrence(&from, &until).unwrap().date,
+                   date::new_date(2024, 12, 25));
+        assert_eq!(calendar.entries[3].next_occurrence(&from, &until), None);
+    }
+
+    #[test]
+    fn calendar_from_path_test() {
+        let error = Calendar::from_path(Path::new("/nonexistent/calendar"), Easter::Western);
+        assert_eq!(error.err().unwrap().exit_code(), 4);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("calendar"), "#include holidays\n* Mar 9, Alice\n").unwrap();
+        std::fs::write(dir.join("holidays"), "# Holidays\n* Dec 25, Christmas\n* Jly 4, x\n")
+            .unwrap();
+        let calendar = Calendar::from_path(&dir.join("calendar"), Easter::Western).unwrap();
+        let entries: Vec<_> = calendar.entries.iter()
+            .map(|entry| (entry.file.rsplit('/').next().unwrap(), entry.line_number))
+            .collect();
+        assert_eq!(entries, vec![("calendar", 2), ("holidays", 2)]);
+        assert_eq!(calendar.diagnostics.len(), 1);
+        assert_eq!(calendar.diagnostics[0].file, dir.join("holidays").display().to_string());
+        assert_eq!(calendar.diagnostics[0].line_number, 3);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::date;
+use crate::datecalc::DateChecker;
+use crate::diagnostic::Diagnostic;
+use crate::easter::Easter;
+use crate::error::Error;
+use crate::include;
+use crate::utils;
+use std::collections::HashMap;
+use std::path::Path;
+
+// check_calendar checks lines of a calendar file and returns problems found
+// in them: lines that can't be parsed, date patterns that can never match,
+// like * Feb 30, w=8 or m=jan & z=200, and duplicate entries.
+pub fn check_calendar<'a, It: Iterator<Item=&'a str>>(
+    file: &str, lines: It, easter: Easter, today: &date::Date) -> Vec<Diagnostic>
+{
+    let mut diagnostics = Vec::new();
+    // Lines seen so far, with whitespace normalized, and their numbers.
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    for (index, line) in lines.enumerate() {
+        let line_number = index + 1;
+        let checker = match utils::parse_calendar_entry(line, easter) {
+            Ok(Some((checker, _, _))) => checker,
+            Ok(None) => continue,
+            Err(error) => {
+                diagnostics.push(Diagnostic::new(
+                    file, line_number, line, error.span, &error.message));
+                continue;
+            }
+        };
+
+        // The date pattern is at the start of the line.
+        let pattern = utils::parse_calendar_line(line).map(|(pattern, _)| pattern)
+            .unwrap_or_default();
+        let pattern_span = pattern.len() - pattern.trim_start().len()..pattern.trim_end().len();
+
+        let impossible = checker.impossible_values(&pattern);
+        for (message, span) in &impossible {
+            diagnostics.push(Diagnostic::new(file, line_number, line, span.clone(), message));
+        }
+        if impossible.is_empty() && never_matches(&checker, today) {
+            diagnostics.push(Diagnostic::new(
+                file, line_number, line, pattern_span.clone(), "Date pattern never matches"));
+        }
+
+        let normalized = line.split_whitespace().collect::<Vec<&str>>().join(" ");
+        if let Some(first) = seen.get(&normalized) {
+            let message = format!("Duplicate of line {}", first);
+            diagnostics.push(Diagnostic::new(file, line_number, line, pattern_span, &message));
+        } else {
+            seen.insert(normalized, line_number);
+        }
+    }
+    diagnostics
+}
+
+// check_path checks the calendar file at path and the files that it
+// includes, each with check_calendar.
+pub fn check_path(path: &Path, easter: Easter, today: &date::Date) ->
+    Result<Vec<Diagnostic>, Error>
+{
+    let (files, mut diagnostics) = include::read_files(path)?;
+    for file in &files {
+        diagnostics.extend(check_calendar(&file.name, file.text.lines(), easter, today));
+    }
+    Ok(diagnostics)
+}
+
+// never_matches returns true if the checker doesn't match any date before
+// or after today. Like occurrences, it looks for dates only up to 400
+// years past the years that the checker refers to.
+fn never_matches(checker: &DateChecker, today: &date::Date) -> bool {
+    checker.occurrences(today).next().is_none() &&
+        checker.occurrences_backward(today).next().is_none()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(lines: &[&str]) -> Vec<(usize, String)> {
+        let today = date::new_date(2021, 9, 27);
+        check_calendar("calendar", lines.iter().copied(), Easter::Western, &today)
+            .into_iter()
+            .map(|d| (d.line_number, d.message))
+            .collect()
+    }
+
+    #[test]
+    fn check_calendar_test() {
+        let lines = [
+            "# Holidays",
+            "* Jan 1, New Year's Day",
+            "* Feb 30, Impossible",
+            "w=8, Impossible",
+            "m=13 & d=1, Impossible",
+            "z=367, Impossible",
+            "m=jan & z=200, Never",
+            "* ju 4, Ambiguous",
+            "2023 Feb 29, Not a leap year",
+            "*   Jan 1,   New Year's Day",
+            "1999 Jun 17, In the past",
+            "y<2000 & m=jun & d=17, In the past",
+        ];
+        assert_eq!(messages(&lines), vec![
+            (3, "Feb 30 doesn't exist".to_string()),
+            (4, "w=8 is never true: w is from 1 to 7".to_string()),
+            (5, "m=13 is never true: m is from 1 to 12".to_string()),
+            (6, "z=367 is never true: z is from 1 to 366".to_string()),
+            (7, "Date pattern never matches".to_string()),
+            (8, "Ambiguous month ju: could be june or july".to_string()),
+            (9, "Date pattern never matches".to_string()),
+            (10, "Duplicate of line 2".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn check_calendar_spans_test() {
+        let lines = [
+            "* Feb 30, Impossible",
+            "d=1 & w=8, Impossible",
+            "  m=jan & z=200 , Never",
+            "* Jan 1, New Year's Day",
+            "*  Jan 1,  New Year's Day",
+        ];
+        let today = date::new_date(2021, 9, 27);
+        let spans: Vec<_> = check_calendar("calendar", lines.iter().copied(), Easter::Western, &today)
+            .into_iter()
+            .map(|d| (d.line_number, d.span))
+            .collect();
+        assert_eq!(spans, vec![(1, 2..8), (2, 6..9), (3, 2..15), (5, 0..8)]);
+    }
+
+    #[test]
+    fn check_calendar_without_problems_test() {
+        let lines = [
+            "* Feb 29, Leap day",
+            "m=may & w=1 & b=1, Memorial Day",
+            "e=-2, Good Friday",
+            "j%14=3, Trash",
+            "2024 Jun 3 .. 2024 Jun 7, PyCon",
+            "y%400=0 & y>2000 & m=feb & d=29, Leap day of a century",
+            "j%20000=0 & y>2030, Every 20000 days",
+        ];
+        assert!(messages(&lines).is_empty());
+    }
+
+    #[test]
+    fn check_calendar_extreme_years_test() {
+        let lines = [
+            "y=2147483647, Far future",
+            "y=-2147483648, Far past",
+            "y=9223372036854775807, Too far for i32",
+            "y=262142 & m=dec & d=31, Last date",
+        ];
+        assert_eq!(messages(&lines), vec![
+            (1, "Date pattern never matches".to_string()),
+            (2, "Date pattern never matches".to_string()),
+            (3, "Date pattern never matches".to_string()),
+        ]);
+    }
+}
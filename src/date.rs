@@ -1,20 +1,75 @@
-use chrono::{NaiveDate};
+use chrono::{Datelike, NaiveDate, NaiveTime};
+use std::fmt;
 
 pub type Date = chrono::NaiveDate;
+pub type Time = chrono::NaiveTime;
 
+// Time of a calendar item, like 09:30 or 09:30-10:00.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct TimeRange {
+    pub start: Time,
+    pub end: Option<Time>,
+}
+
+impl TimeRange {
+    // parse parses time like 9:30 or time range like 09:30-10:00. The end
+    // of the range can't be before its start.
+    pub fn parse(s: &str) -> Option<TimeRange> {
+        let parse_time = |s: &str| NaiveTime::parse_from_str(s.trim(), "%H:%M").ok();
+        match s.split_once('-') {
+            Some((start, end)) => {
+                let start = parse_time(start)?;
+                let end = parse_time(end)?;
+                if end < start {
+                    return None;
+                }
+                Some(TimeRange { start, end: Some(end) })
+            },
+            None => Some(TimeRange { start: parse_time(s)?, end: None }),
+        }
+    }
+}
+
+impl fmt::Display for TimeRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.start.format("%H:%M"))?;
+        if let Some(end) = self.end {
+            write!(f, "-{}", end.format("%H:%M"))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
 pub fn new_date(year: i32, month: u32, day: u32) -> Date {
-    NaiveDate::from_ymd(year, month, day)
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+// new_date_opt returns None for dates that don't exist, such as 2021 Feb 30.
+pub fn new_date_opt(year: i32, month: u32, day: u32) -> Option<Date> {
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+pub fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .and_then(|d| d.pred_opt())
+        .map(|d| d.day())
+        .unwrap_or(31)
 }
-                    
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::{Datelike, Duration, Local};
+    use chrono::{Duration, Local};
 
     #[test]
     fn date_calculation() {
-        let date = Local::today();
+        let date = Local::now().date_naive();
         let date2 = date + Duration::days(3);
         assert_eq!(date2.signed_duration_since(date).num_days(), 3);
         assert_eq!(date2 - date, Duration::days(3));
@@ -27,4 +82,35 @@ mod tests {
         assert_eq!(date.month(), 6);
         assert_eq!(date.day(), 17);
     }
+
+    #[test]
+    fn time_range_test() {
+        let t = TimeRange::parse("9:30").unwrap();
+        assert_eq!(t.start, NaiveTime::from_hms_opt(9, 30, 0).unwrap());
+        assert_eq!(t.end, None);
+        assert_eq!(t.to_string(), "09:30");
+
+        let t = TimeRange::parse("09:30 - 10:00").unwrap();
+        assert_eq!(t.end, Some(NaiveTime::from_hms_opt(10, 0, 0).unwrap()));
+        assert_eq!(t.to_string(), "09:30-10:00");
+
+        assert!(TimeRange::parse("10:00-09:30").is_none());
+        assert!(TimeRange::parse("25:00").is_none());
+        assert!(TimeRange::parse("Standup").is_none());
+
+        // Earlier start comes first; time without end before the range.
+        assert!(TimeRange::parse("09:30").unwrap() < TimeRange::parse("14:00").unwrap());
+        assert!(TimeRange::parse("09:30").unwrap() < TimeRange::parse("09:30-10:00").unwrap());
+    }
+
+    #[test]
+    fn days_in_month_test() {
+        assert_eq!(days_in_month(2021, 1), 31);
+        assert_eq!(days_in_month(2021, 2), 28);
+        assert_eq!(days_in_month(2024, 2), 29);
+        assert_eq!(days_in_month(1900, 2), 28);
+        assert_eq!(days_in_month(2000, 2), 29);
+        assert_eq!(days_in_month(2021, 4), 30);
+        assert_eq!(days_in_month(2021, 12), 31);
+    }
 }
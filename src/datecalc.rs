@@ -1,315 +1,1064 @@
 use crate::date;
+use crate::easter::Easter;
+use crate::parser::{self, ParseError};
+use crate::planner::Plan;
 use chrono::{Duration, Datelike};
 use regex::Regex;
-
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+// Check of one field of a date pattern, which is * for any value, a value
+// like 15 or jun, a range like 1-7 or jan-mar, a range with a step like */5
+// or 1-15/2, or a list of those like jun,aug.
+#[derive(PartialEq, Debug, Clone)]
 pub enum NumberCheck
 {
     Any,
-    Match(u32)
+    Match(u32),
+    Range {
+        first: u32,
+        last: u32,
+        step: u32,
+    },
+    List(Vec<NumberCheck>),
 }
 
 impl NumberCheck {
-    fn check(&self, n: u32) -> bool {
-        match *self {
-            NumberCheck::Match(i) => i == n,
-            NumberCheck::Any => true
+    // format formats the check like in date patterns, with values formatted
+    // by name, e.g. as month names.
+    fn format(&self, name: &dyn Fn(u32) -> String) -> String {
+        match self {
+            NumberCheck::Any => "*".to_string(),
+            NumberCheck::Match(n) => name(*n),
+            // Ranges without the last value come from fields like 2020/4,
+            // which are formatted the same way, even with step 1.
+            NumberCheck::Range { first, last: u32::MAX, step } => {
+                format!("{}/{}", name(*first), step)
+            },
+            NumberCheck::Range { first, last, step } => {
+                let step = if *step == 1 { String::new() } else { format!("/{}", step) };
+                format!("{}-{}{}", name(*first), name(*last), step)
+            },
+            NumberCheck::List(checks) => {
+                checks.iter().map(|c| c.format(name)).collect::<Vec<String>>().join(",")
+            },
+        }
+    }
+
+    pub(crate) fn check(&self, n: u32) -> bool {
+        match self {
+            NumberCheck::Match(i) => *i == n,
+            NumberCheck::Any => true,
+            NumberCheck::Range { first, last, step } => {
+                n >= *first && n <= *last && (n - first).is_multiple_of(*step)
+            },
+            NumberCheck::List(v) => v.iter().any(|c| c.check(n)),
+        }
+    }
+
+    // next_value returns the smallest value at least n that the check
+    // matches.
+    pub(crate) fn next_value(&self, n: u32) -> Option<u32> {
+        match self {
+            NumberCheck::Any => Some(n),
+            NumberCheck::Match(i) => Some(*i).filter(|i| *i >= n),
+            NumberCheck::Range { first, last, step } => {
+                let value = if n <= *first {
+                    *first
+                } else {
+                    let steps = (n - first).div_ceil(*step);
+                    steps.checked_mul(*step).and_then(|s| first.checked_add(s))?
+                };
+                Some(value).filter(|v| v <= last)
+            },
+            NumberCheck::List(v) => v.iter().filter_map(|c| c.next_value(n)).min(),
+        }
+    }
+
+    // previous_value returns the largest value at most n that the check
+    // matches.
+    pub(crate) fn previous_value(&self, n: u32) -> Option<u32> {
+        match self {
+            NumberCheck::Any => Some(n),
+            NumberCheck::Match(i) => Some(*i).filter(|i| *i <= n),
+            NumberCheck::Range { first, last, step } => {
+                let n = n.min(*last);
+                if n < *first {
+                    return None;
+                }
+                Some(n - (n - first) % step)
+            },
+            NumberCheck::List(v) => v.iter().filter_map(|c| c.previous_value(n)).max(),
+        }
+    }
+
+    // values_outside adds to v values in the check that are outside of the
+    // range from min to max.
+    fn values_outside(&self, min: u32, max: u32, v: &mut Vec<u32>) {
+        let mut add = |n: u32| if n < min || n > max { v.push(n) };
+        match self {
+            NumberCheck::Any => {},
+            NumberCheck::Match(n) => add(*n),
+            NumberCheck::Range { first, last, .. } => {
+                add(*first);
+                if *last != u32::MAX {
+                    add(*last);
+                }
+            },
+            NumberCheck::List(checks) => {
+                for c in checks {
+                    c.values_outside(min, max, v);
+                }
+            },
+        }
+    }
+
+    // values returns up to count values that the check matches, in the
+    // order they appear in it. It returns no values for *.
+    fn values(&self, count: usize) -> Vec<u32> {
+        match self {
+            NumberCheck::Any => vec![],
+            NumberCheck::Match(n) => vec![*n],
+            NumberCheck::Range { first, last, step } => {
+                (0..count as u32)
+                    .map_while(|k| k.checked_mul(*step).and_then(|s| first.checked_add(s)))
+                    .take_while(|n| n <= last)
+                    .collect()
+            },
+            NumberCheck::List(checks) => {
+                checks.iter().flat_map(|c| c.values(count)).take(count).collect()
+            },
         }
     }
 }
 
-#[derive(PartialEq, Debug)]
+impl fmt::Display for NumberCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(&|n| n.to_string()))
+    }
+}
+
+// Variables that can be used in date expressions.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Variable {
+    W, // day of the week (1 for Monday, 7 for Sunday)
+    M, // month
+    D, // day of the month
+    Y, // year
+    A, // 1 for the first 7 days of the month, 2 for the next 7, etc.
+    // a as computed by earlier versions of when-rs: 1 for days 1-6,
+    // 2 for days 7-13, etc. It can't be used in expressions; it is only
+    // used to warn about lines whose matches changed.
+    LegacyA,
+    B, // 1 for the last 7 days of the month, 2 for the previous 7, etc.
+    Z, // day of the year (1 on New Year Day)
+    E(Easter), // days relative to Easter (0 on Easter Sunday, -2 on Good Friday)
+    J, // modified Julian day (0 on 1858 Nov 17)
+}
+
+// Value of chrono's num_days_from_ce on 1858 Nov 17, day 0 of the modified
+// Julian day count.
+const MJD_EPOCH_DAYS_FROM_CE: i64 = 678576;
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Variable::W => "w",
+            Variable::M => "m",
+            Variable::D => "d",
+            Variable::Y => "y",
+            Variable::A | Variable::LegacyA => "a",
+            Variable::B => "b",
+            Variable::Z => "z",
+            Variable::E(_) => "e",
+            Variable::J => "j",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Variable {
+    // range returns the smallest and the largest value of the variable, or
+    // None if it isn't limited.
+    pub fn range(&self) -> Option<(i64, i64)> {
+        match self {
+            Variable::W => Some((1, 7)),
+            Variable::M => Some((1, 12)),
+            Variable::D => Some((1, 31)),
+            Variable::A | Variable::LegacyA | Variable::B => Some((1, 5)),
+            Variable::Z => Some((1, 366)),
+            Variable::Y | Variable::E(_) | Variable::J => None,
+        }
+    }
+
+    pub fn from_name(name: &str, easter: Easter) -> Option<Variable> {
+        match name {
+            "w" => Some(Variable::W),
+            "m" => Some(Variable::M),
+            "d" => Some(Variable::D),
+            "y" => Some(Variable::Y),
+            "a" => Some(Variable::A),
+            "b" => Some(Variable::B),
+            "z" => Some(Variable::Z),
+            "e" => Some(Variable::E(easter)),
+            "j" => Some(Variable::J),
+            _ => None,
+        }
+    }
+
+    pub fn value(&self, date: &date::Date) -> i64 {
+        match self {
+            Variable::W => date.weekday().number_from_monday() as i64,
+            Variable::M => date.month() as i64,
+            Variable::D => date.day() as i64,
+            Variable::Y => date.year() as i64,
+            Variable::A => ((date.day() - 1) / 7 + 1) as i64,
+            Variable::LegacyA => ((date.day() / 7) + 1) as i64,
+            Variable::B => {
+                let last = date::days_in_month(date.year(), date.month());
+                ((last - date.day()) / 7 + 1) as i64
+            },
+            Variable::Z => date.ordinal() as i64,
+            Variable::E(easter) => {
+                date.signed_duration_since(easter.date(date.year())).num_days()
+            },
+            Variable::J => date.num_days_from_ce() as i64 - MJD_EPOCH_DAYS_FROM_CE,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum BinaryOperator {
+    Or,
+    And,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Add,
+    Subtract,
+    Modulo,
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            BinaryOperator::Or => "|",
+            BinaryOperator::And => "&",
+            BinaryOperator::Equal => "=",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::Less => "<",
+            BinaryOperator::LessEqual => "<=",
+            BinaryOperator::Greater => ">",
+            BinaryOperator::GreaterEqual => ">=",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Modulo => "%",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl BinaryOperator {
+    // precedence returns the precedence of the operator; operators with
+    // higher precedence bind tighter, see the grammar in parser.rs.
+    fn precedence(&self) -> u32 {
+        match self {
+            BinaryOperator::Or => 1,
+            BinaryOperator::And => 2,
+            BinaryOperator::Equal | BinaryOperator::NotEqual |
+            BinaryOperator::Less | BinaryOperator::LessEqual |
+            BinaryOperator::Greater | BinaryOperator::GreaterEqual => 4,
+            BinaryOperator::Add | BinaryOperator::Subtract => 5,
+            BinaryOperator::Modulo => 6,
+        }
+    }
+
+    fn apply(&self, left: i64, right: i64) -> i64 {
+        match self {
+            BinaryOperator::Or => ((left != 0) || (right != 0)) as i64,
+            BinaryOperator::And => ((left != 0) && (right != 0)) as i64,
+            BinaryOperator::Equal => (left == right) as i64,
+            BinaryOperator::NotEqual => (left != right) as i64,
+            BinaryOperator::Less => (left < right) as i64,
+            BinaryOperator::LessEqual => (left <= right) as i64,
+            BinaryOperator::Greater => (left > right) as i64,
+            BinaryOperator::GreaterEqual => (left >= right) as i64,
+            BinaryOperator::Add => left.wrapping_add(right),
+            BinaryOperator::Subtract => left.wrapping_sub(right),
+            // Modulo is never negative, so j%14=3 holds every 14 days even
+            // for negative j. Modulo by zero evaluates to 0.
+            BinaryOperator::Modulo => left.checked_rem_euclid(right).unwrap_or(0),
+        }
+    }
+}
+
+// Date expression, such as (m=dec & d>=24) | y%4=0. Like in the Perl when,
+// all values are integers; comparisons and logical operators evaluate to
+// 1 for true and 0 for false, and any non-zero value counts as true.
+#[derive(PartialEq, Debug, Clone)]
 pub enum DateExpression {
-    W(u16), // day of the week
-    M(u16), // month
-    D(u16), // day of the month
-    Y(u16), // year
-    A(u16), // 1 for the first 7 days of the month, 2 for the next 7, etc.
-    Z(u16), // day of the year (1 on New Year Day)
-} 
+    Number(i64),
+    Variable(Variable),
+    Not(Box<DateExpression>),
+    Negate(Box<DateExpression>),
+    Binary(BinaryOperator, Box<DateExpression>, Box<DateExpression>),
+}
 
 impl DateExpression {
+    // precedence returns the precedence of the outermost operator of the
+    // expression, like BinaryOperator::precedence.
+    fn precedence(&self) -> u32 {
+        match self {
+            DateExpression::Binary(op, _, _) => op.precedence(),
+            DateExpression::Not(_) => 3,
+            DateExpression::Negate(_) => 7,
+            DateExpression::Number(_) | DateExpression::Variable(_) => 8,
+        }
+    }
+
+    // variables adds to v variables used in the expression that aren't in
+    // it yet.
+    pub(crate) fn variables(&self, v: &mut Vec<Variable>) {
+        match self {
+            DateExpression::Number(_) => {},
+            DateExpression::Variable(var) => {
+                if !v.contains(var) {
+                    v.push(*var);
+                }
+            },
+            DateExpression::Not(e) | DateExpression::Negate(e) => e.variables(v),
+            DateExpression::Binary(_, e1, e2) => {
+                e1.variables(v);
+                e2.variables(v);
+            },
+        }
+    }
+
+    pub fn evaluate(&self, date: &date::Date) -> i64 {
+        match self {
+            DateExpression::Number(n) => *n,
+            DateExpression::Variable(v) => v.value(date),
+            DateExpression::Not(e) => (e.evaluate(date) == 0) as i64,
+            DateExpression::Negate(e) => e.evaluate(date).wrapping_neg(),
+            DateExpression::Binary(BinaryOperator::And, e1, e2) => {
+                (e1.check(date) && e2.check(date)) as i64
+            },
+            DateExpression::Binary(BinaryOperator::Or, e1, e2) => {
+                (e1.check(date) || e2.check(date)) as i64
+            },
+            DateExpression::Binary(op, e1, e2) => {
+                op.apply(e1.evaluate(date), e2.evaluate(date))
+            },
+        }
+    }
+
     fn check(&self, date: &date::Date) -> bool {
+        self.evaluate(date) != 0
+    }
+
+    // impossible_terms adds to v messages about terms like w=8, which
+    // compare variable with a value it never has, with the variable and the
+    // value.
+    fn impossible_terms(&self, v: &mut Vec<(String, Variable, i64)>) {
         match self {
-            DateExpression::W(w) => {
-                let weekday = date.weekday().number_from_monday();
-                return (*w as u32) == weekday;
+            DateExpression::Binary(BinaryOperator::Equal, e1, e2) => {
+                if let (DateExpression::Variable(var), DateExpression::Number(n)) |
+                       (DateExpression::Number(n), DateExpression::Variable(var)) = (&**e1, &**e2)
+                {
+                    if let Some((min, max)) = var.range() {
+                        if *n < min || *n > max {
+                            let message = format!("{}={} is never true: {} is from {} to {}",
+                                                  var, n, var, min, max);
+                            v.push((message, *var, *n));
+                        }
+                    }
+                }
+                e1.impossible_terms(v);
+                e2.impossible_terms(v);
             },
-            DateExpression::M(m) => {
-                return (*m as u32) == date.month();
+            DateExpression::Binary(_, e1, e2) => {
+                e1.impossible_terms(v);
+                e2.impossible_terms(v);
             },
-            DateExpression::D(d) => {
-                return (*d as u32) == date.day();
+            DateExpression::Not(e) | DateExpression::Negate(e) => e.impossible_terms(v),
+            DateExpression::Number(_) | DateExpression::Variable(_) => {},
+        }
+    }
+
+    // mentioned_years adds to v years that the expression compares y with,
+    // and years of modified Julian days that it compares j with.
+    fn mentioned_years(&self, v: &mut Vec<i32>) {
+        match self {
+            DateExpression::Binary(_, e1, e2) => {
+                if let (DateExpression::Variable(var), DateExpression::Number(n)) |
+                       (DateExpression::Number(n), DateExpression::Variable(var)) = (&**e1, &**e2)
+                {
+                    match var {
+                        Variable::Y => v.extend(i32::try_from(*n).ok()),
+                        Variable::J => {
+                            let days = n.saturating_add(MJD_EPOCH_DAYS_FROM_CE);
+                            if let Some(date) = i32::try_from(days).ok()
+                                .and_then(date::Date::from_num_days_from_ce_opt)
+                            {
+                                v.push(date.year());
+                            }
+                        },
+                        _ => {},
+                    }
+                }
+                e1.mentioned_years(v);
+                e2.mentioned_years(v);
+            },
+            DateExpression::Not(e) | DateExpression::Negate(e) => e.mentioned_years(v),
+            DateExpression::Number(_) | DateExpression::Variable(_) => {},
+        }
+    }
+
+    // bounds returns the smallest and the largest value of variable for
+    // which the expression can be true, from comparisons of the variable
+    // with numbers, like m=dec or d>=15, combined with & and |. Other
+    // expressions don't limit the values.
+    pub(crate) fn bounds(&self, variable: Variable) -> (i64, i64) {
+        let all = (i64::MIN, i64::MAX);
+        match self {
+            DateExpression::Binary(BinaryOperator::And, e1, e2) => {
+                let ((min1, max1), (min2, max2)) = (e1.bounds(variable), e2.bounds(variable));
+                (min1.max(min2), max1.min(max2))
+            },
+            DateExpression::Binary(BinaryOperator::Or, e1, e2) => {
+                let ((min1, max1), (min2, max2)) = (e1.bounds(variable), e2.bounds(variable));
+                (min1.min(min2), max1.max(max2))
+            },
+            DateExpression::Binary(op, e1, e2) => {
+                // Comparison like 15<=d is turned around to d>=15.
+                let (op, n) = match (&**e1, &**e2) {
+                    (DateExpression::Variable(v), DateExpression::Number(n)) if *v == variable => {
+                        (*op, *n)
+                    },
+                    (DateExpression::Number(n), DateExpression::Variable(v)) if *v == variable => {
+                        let op = match op {
+                            BinaryOperator::Less => BinaryOperator::Greater,
+                            BinaryOperator::LessEqual => BinaryOperator::GreaterEqual,
+                            BinaryOperator::Greater => BinaryOperator::Less,
+                            BinaryOperator::GreaterEqual => BinaryOperator::LessEqual,
+                            op => *op,
+                        };
+                        (op, *n)
+                    },
+                    _ => return all,
+                };
+                match op {
+                    BinaryOperator::Equal => (n, n),
+                    BinaryOperator::Less => (i64::MIN, n.saturating_sub(1)),
+                    BinaryOperator::LessEqual => (i64::MIN, n),
+                    BinaryOperator::Greater => (n.saturating_add(1), i64::MAX),
+                    BinaryOperator::GreaterEqual => (n, i64::MAX),
+                    _ => all,
+                }
+            },
+            _ => all,
+        }
+    }
+
+    fn uses_variable(&self, variable: Variable) -> bool {
+        match self {
+            DateExpression::Number(_) => false,
+            DateExpression::Variable(v) => *v == variable,
+            DateExpression::Not(e) | DateExpression::Negate(e) => e.uses_variable(variable),
+            DateExpression::Binary(_, e1, e2) => {
+                e1.uses_variable(variable) || e2.uses_variable(variable)
+            },
+        }
+    }
+
+    // replace_variable returns a copy of the expression with variable from
+    // replaced by variable to.
+    fn replace_variable(&self, from: Variable, to: Variable) -> DateExpression {
+        let replace = |e: &DateExpression| Box::new(e.replace_variable(from, to));
+        match self {
+            DateExpression::Number(n) => DateExpression::Number(*n),
+            DateExpression::Variable(v) if *v == from => DateExpression::Variable(to),
+            DateExpression::Variable(v) => DateExpression::Variable(*v),
+            DateExpression::Not(e) => DateExpression::Not(replace(e)),
+            DateExpression::Negate(e) => DateExpression::Negate(replace(e)),
+            DateExpression::Binary(op, e1, e2) => {
+                DateExpression::Binary(*op, replace(e1), replace(e2))
             },
-            DateExpression::Y(y) => {
-                return (*y as i32) == date.year();
+        }
+    }
+}
+
+impl fmt::Display for DateExpression {
+    // fmt formats the expression with as few parentheses as the grammar
+    // allows, e.g. as (m=12 & d>=24) | !w=6.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // operand writes e, in parentheses if it binds less tightly than
+        // precedence.
+        let operand = |f: &mut fmt::Formatter, e: &DateExpression, precedence: u32| {
+            if e.precedence() < precedence {
+                write!(f, "({})", e)
+            } else {
+                write!(f, "{}", e)
+            }
+        };
+        match self {
+            DateExpression::Number(n) => write!(f, "{}", n),
+            DateExpression::Variable(v) => write!(f, "{}", v),
+            DateExpression::Not(e) => {
+                write!(f, "!")?;
+                operand(f, e, 3)
             },
-            DateExpression::A(a) => {
-                return (date.day() / 7) + 1 == *a as u32;
+            DateExpression::Negate(e) => {
+                write!(f, "-")?;
+                operand(f, e, 7)
             },
-            DateExpression::Z(z) => {
-                return date.ordinal() == *z as u32;
+            DateExpression::Binary(op, e1, e2) => {
+                // Operators are left associative, and comparisons can't be
+                // chained.
+                let precedence = op.precedence();
+                operand(f, e1, if precedence == 4 { 5 } else { precedence })?;
+                match op {
+                    BinaryOperator::And | BinaryOperator::Or => write!(f, " {} ", op)?,
+                    _ => write!(f, "{}", op)?,
+                }
+                operand(f, e2, precedence + 1)
             },
         }
     }
 }
 
+// term_span returns the span in text of the first term like var=n or n=var
+// from byte from on.
+fn term_span(text: &str, from: usize, var: Variable, n: i64) -> Option<Range<usize>> {
+    let pattern = format!(r"(?i)\b{v}\s*==?\s*0*{n}\b|\b0*{n}\s*==?\s*{v}\b", v = var, n = n);
+    Regex::new(&pattern).ok()?.find_at(text, from).map(|m| m.range())
+}
+
+#[derive(PartialEq, Debug, Clone)]
 pub enum DateChecker {
     Spec {
         year: NumberCheck,
         month: NumberCheck,
         day: NumberCheck,
+        weekday: NumberCheck,
     },
     Expr (
-        Vec<DateExpression>,
-    )
+        DateExpression,
+    ),
+    // Event lasting for days, starting on each date matched by start.
+    Span {
+        start: Box<DateChecker>,
+        days: u32,
+    },
 }
 
-fn parse_number_expression(s: &str) -> Option<NumberCheck> {
-    match s.parse::<u32>() {
-        Ok(v) => Some(NumberCheck::Match(v)),
-        Err(_) => {
-            if s == "*" {
-                Some(NumberCheck::Any)
-            } else {
-                None
-            }
+// parse_field parses a field of a date pattern, such as 1-7,15 or */5.
+// Values are parsed with parse_value; min and max are the smallest and the
+// largest value of the field, used for * and for ranges like 10/2 without
+// the last value.
+fn parse_field(s: &str, min: u32, max: u32, parse_value: &dyn Fn(&str) -> Option<u32>) ->
+    Option<NumberCheck>
+{
+    let items: Option<Vec<NumberCheck>> = s.split(',')
+        .map(|item| parse_field_item(item, min, max, parse_value))
+        .collect();
+    let mut items = items?;
+    if items.len() == 1 {
+        return items.pop();
+    }
+    Some(NumberCheck::List(items))
+}
+
+fn parse_field_item(s: &str, min: u32, max: u32, parse_value: &dyn Fn(&str) -> Option<u32>) ->
+    Option<NumberCheck>
+{
+    let (base, step) = match s.split_once('/') {
+        Some((base, step)) => (base, Some(step.parse::<u32>().ok().filter(|n| *n > 0)?)),
+        None => (s, None),
+    };
+    let (first, last) = if base == "*" {
+        if step.is_none() {
+            return Some(NumberCheck::Any);
+        }
+        (min, max)
+    } else if let Some((first, last)) = base.split_once('-') {
+        (parse_value(first)?, parse_value(last)?)
+    } else {
+        let value = parse_value(base)?;
+        if step.is_none() {
+            return Some(NumberCheck::Match(value));
         }
+        (value, max)
+    };
+    if first > last {
+        return None;
     }
+    Some(NumberCheck::Range { first, last, step: step.unwrap_or(1) })
+}
+
+fn parse_number(s: &str) -> Option<u32> {
+    s.parse::<u32>().ok()
 }
 
-fn parse_month(s: &str) -> Option<u8> {
+fn parse_number_expression(s: &str) -> Option<NumberCheck> {
+    parse_field(s, 0, u32::MAX, &parse_number)
+}
+
+const MONTHS: [(u8, &str); 12] = [
+    (1, "january"),
+    (2, "february"),
+    (3, "march"),
+    (4, "april"),
+    (5, "may"),
+    (6, "june"),
+    (7, "july"),
+    (8, "august"),
+    (9, "september"),
+    (10, "october"),
+    (11, "november"),
+    (12, "december"),
+];
+
+const WEEKDAYS: [(u8, &str); 7] = [
+    (1, "monday"),
+    (2, "tuesday"),
+    (3, "wednesday"),
+    (4, "thursday"),
+    (5, "friday"),
+    (6, "saturday"),
+    (7, "sunday"),
+];
+
+// prefix_matches returns names that start with s, ignoring case.
+fn prefix_matches<'a>(s: &str, names: &[(u8, &'a str)]) -> Vec<(u8, &'a str)> {
     let ls = s.to_lowercase();
-    let months = vec![
-        (1, "january"),
-        (2, "february"),
-        (3, "march"),
-        (4, "april"),
-        (5, "may"),
-        (6, "june"),
-        (7, "july"),
-        (8, "august"),
-        (9, "september"),
-        (10, "october"),
-        (11, "november"),
-        (12, "december"),
-    ];
-    let matches: Vec<(u8, &str)> = months.iter()
+    names.iter()
         .filter(|(_, m)| (*m).starts_with(&ls))
         .map(|(ind, m)| (*ind, *m))
-        .collect();
+        .collect()
+}
+
+// parse_unique_prefix returns the number of the name that starts with s,
+// ignoring case, as long as there is exactly one such name.
+fn parse_unique_prefix(s: &str, names: &[(u8, &str)]) -> Option<u8> {
+    let matches = prefix_matches(s, names);
 
-    if matches.len() == 0 {
+    if matches.is_empty() {
         // No match
         return None;
     }
     if matches.len() > 1 {
-        // More than one match; we don't know which month.
+        // More than one match; we don't know which one.
         return None;
     }
     Some(matches[0].0)
 }
 
-fn parse_month_expression(s: &str) -> Option<NumberCheck> {
-    if let Some(n) = parse_month(s) {
-        return Some(NumberCheck::Match(n as u32));
+pub fn parse_month(s: &str) -> Option<u8> {
+    parse_unique_prefix(s, &MONTHS)
+}
+
+// parse_weekday parses a weekday name or its unique prefix, like fri or Tu.
+// Weekdays are numbered the same as variable w: 1 for Monday, 7 for Sunday.
+pub fn parse_weekday(s: &str) -> Option<u8> {
+    parse_unique_prefix(s, &WEEKDAYS)
+}
+
+// ambiguous_name returns the message explaining that name, or a name in
+// the field of a date pattern like ju,aug, is a prefix of more than one
+// month or weekday name. It returns None if there is no such name.
+pub fn ambiguous_name(s: &str) -> Option<String> {
+    for name in s.split([',', '-', '/']) {
+        if name.is_empty() || !name.chars().all(char::is_alphabetic) {
+            continue;
+        }
+        for (kind, names) in [("month", &MONTHS[..]), ("weekday", &WEEKDAYS[..])] {
+            let matches = prefix_matches(name, names);
+            if matches.len() > 1 {
+                let candidates: Vec<&str> = matches.iter().map(|(_, m)| *m).collect();
+                return Some(format!("Ambiguous {} {}: could be {}",
+                                    kind, name, candidates.join(" or ")));
+            }
+        }
     }
     None
 }
 
-fn get_date_range(date1: &date::Date, date2: &date::Date) -> Vec<date::Date> {
-    let d1 = date1.num_days_from_ce();
-    let d2 = date2.num_days_from_ce();
-    let diff = d2 - d1;
-    let mut v: Vec<date::Date> = Vec::new();
-    for d in 0..=diff {
-        let date = *date1 + Duration::days(d as i64);
-        v.push(date);
+fn parse_month_expression(s: &str) -> Option<NumberCheck> {
+    let parse_value = |s: &str| parse_number(s).or_else(|| parse_month(s).map(u32::from));
+    parse_field(s, 1, 12, &parse_value)
+}
+
+// parse_day_expression parses the day of the date pattern, which is either
+// days of the month, like 1-7, or weekdays, like in * * fri or * * mon-fri.
+// It returns the checks for the day of the month and the weekday.
+fn parse_day_expression(s: &str) -> Option<(NumberCheck, NumberCheck)> {
+    if let Some(day) = parse_field(s, 1, 31, &parse_number) {
+        return Some((day, NumberCheck::Any));
     }
-    v
+    let parse_value = |s: &str| parse_weekday(s).map(u32::from);
+    parse_field(s, 1, 7, &parse_value).map(|w| (NumberCheck::Any, w))
 }
 
-impl DateChecker {
-    pub fn new(expr: &str) -> Result<DateChecker, String> {
-        if (expr.find('=') == None) && (expr.find('&') == None) {
-            let re = Regex::new(r"\s+").unwrap();
-            let split: Vec<&str> = re.split(expr).collect();
-            if split.len() != 3 {
-                return Err(String::from("Bad date expression"));
-            }
-            let year = parse_number_expression(split[0]);
-            if year.is_none() {
-                return Err(String::from("Bad year"));
-            }
-            let year = year.unwrap();
+// words returns the words of s, which are separated by whitespace.
+fn words(s: &str) -> Vec<regex::Match<'_>> {
+    static WORD: OnceLock<Regex> = OnceLock::new();
+    WORD.get_or_init(|| Regex::new(r"\S+").unwrap()).find_iter(s).collect()
+}
 
-            let month = parse_month_expression(split[1]);
-            if month.is_none() {
-                return Err(String::from("Bad month"));
-            }
-            let month = month.unwrap();
+// fields_error returns the error for a date pattern that doesn't have
+// exactly three fields, split: it spans the extra fields, if any.
+fn fields_error(s: &str, split: &[regex::Match]) -> ParseError {
+    let span = match split.get(3) {
+        Some(extra) => extra.start()..s.trim_end().len(),
+        None => s.len()..s.len(),
+    };
+    ParseError::new("Expected year, month and day", span)
+}
 
-            let day = parse_number_expression(split[2]);
-            if day.is_none() {
-                return Err(String::from("Bad day"));
-            }
-            let day = day.unwrap();
+// field_error returns the error for a field of a date pattern, or the
+// error saying which names an ambiguous name like ju could be.
+fn field_error(field: &regex::Match, message: &str) -> ParseError {
+    let message = ambiguous_name(field.as_str())
+        .unwrap_or_else(|| message.to_string());
+    ParseError::new(&message, field.range())
+}
 
-            return Ok(DateChecker::Spec { year, month, day });
-        }
-        // TODO: parse date expression.
-        let re = Regex::new(r"\s+").unwrap();
-        let split: Vec<&str> = re.split(expr).collect();
-        if split.len() % 2 != 1 {
-            // Expression must have form e1 & e2 & ... & en
-            // Where ei are terms like w=3. So there must be an odd number
-            // of words separated by spaces.
-            return Err(String::from("Bad date expression"));
-        }
-        let mut v: Vec<DateExpression> = vec![];
-        let mut i = 0;
-        loop {
-            let re2 = Regex::new("=").unwrap();
-            let term = split[i];
-            // term must have the form like "w=3".
-            let parts: Vec<&str> = re2.split(term).collect();
-            if parts.len() != 2 {
-                return Err(String::from("Bad date expression"));
-            }
+// skip_whitespace returns the position of the first character of s at or
+// after position from that isn't whitespace.
+fn skip_whitespace(s: &str, from: usize) -> usize {
+    s[from..].find(|c: char| !c.is_whitespace())
+        .map(|i| from + i)
+        .unwrap_or(s.len())
+}
 
-            let val = parts[1].parse::<u16>();
-            let term;
-            match parts[0] {
-                "w" => match val {
-                    Ok(w) => term = DateExpression::W(w),
-                    Err(_) => return Err(String::from("Bad date expression")),
-                },
-                "m" => match val {
-                    // TODO: parse month like "jan", instead of a number.
-                    Ok(m) => term = DateExpression::M(m),
-                    Err(_) => {
-                        let month = parse_month(parts[1]);
-                        match month {
-                            Some(m) => term = DateExpression::M(m as u16),
-                            None => return Err(String::from("Bad date expression")),
-                        }
-                    }
-                },
-                "d" => match val {
-                    Ok(d) => term = DateExpression::D(d),
-                    Err(_) => return Err(String::from("Bad date expression")),
-                },
-                "y" => match val {
-                    Ok(y) => term = DateExpression::Y(y),
-                    Err(_) => return Err(String::from("Bad date expression")),
-                },
-                "a" => match val {
-                    Ok(a) => term = DateExpression::A(a),
-                    Err(_) => return Err(String::from("Bad date expression")),
-                },
-                "z" => match val {
-                    Ok(z) => term = DateExpression::Z(z),
-                    Err(_) => return Err(String::from("Bad date expression")),
-                },
-                _   => {
-                    return Err(String::from("Bad date expression"));
+// Number of years past the years that a date pattern mentions in which
+// occurrences looks for matching dates, so that it doesn't look forever for
+// patterns like y=1999. Dates and weekdays repeat every 400 years.
+const YEARS_TO_SEARCH: i32 = 400;
+
+// Iterator over dates that match a date checker, starting with a date and
+// going forward or backward, see DateChecker::occurrences.
+pub struct Dates<'a> {
+    checker: &'a DateChecker,
+    plan: Plan,
+    next: Option<date::Date>,
+    limit: date::Date, // last date to check
+    forward: bool,
+}
+
+impl<'a> Dates<'a> {
+    fn new(checker: &'a DateChecker, from: &date::Date, forward: bool) -> Dates<'a> {
+        let mut years = checker.mentioned_years();
+        years.push(from.year());
+        let limit = if forward {
+            let year = years.iter().max().unwrap().saturating_add(YEARS_TO_SEARCH);
+            date::new_date_opt(year, 12, 31).unwrap_or(date::Date::MAX)
+        } else {
+            let year = years.iter().min().unwrap().saturating_sub(YEARS_TO_SEARCH);
+            date::new_date_opt(year, 1, 1).unwrap_or(date::Date::MIN)
+        };
+        Dates { checker, plan: Plan::new(checker), next: Some(*from), limit, forward }
+    }
+
+    // until stops the iteration at date last, inclusive.
+    pub fn until(mut self, last: date::Date) -> Dates<'a> {
+        self.limit = if self.forward { last.min(self.limit) } else { last.max(self.limit) };
+        self
+    }
+}
+
+impl<'a> Iterator for Dates<'a> {
+    type Item = date::Date;
+
+    fn next(&mut self) -> Option<date::Date> {
+        while let Some(date) = self.next {
+            // Only candidates of the plan can match.
+            let candidate = if self.forward {
+                self.plan.next_date(date, &self.limit)
+            } else {
+                self.plan.previous_date(date, &self.limit)
+            };
+            let date = match candidate {
+                Some(date) => date,
+                None => {
+                    self.next = None;
+                    break;
                 },
+            };
+            self.next = if self.forward { date.succ_opt() } else { date.pred_opt() };
+            if self.checker.check_date(&date) {
+                return Some(date);
             }
-            v.push(term);
+        }
+        None
+    }
+}
 
-            if i + 1 >= split.len() {
-                break;
+impl fmt::Display for DateChecker {
+    // fmt formats the checker as a date pattern or expression, e.g. as
+    // * dec 25, m=12 & d=25 or 2024 jun 3 +5d.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = |names: &'static [(u8, &str)]| {
+            move |n: u32| match names.get((n as usize).wrapping_sub(1)) {
+                Some((_, name)) => name[..3].to_string(),
+                None => n.to_string(),
             }
-            if split[i+1] != "&" {
-                return Err(String::from("Bad date expression"));
-            }
-            i = i + 2;
+        };
+        match self {
+            DateChecker::Spec { year, month, day, weekday } => {
+                let day = match weekday {
+                    NumberCheck::Any => day.to_string(),
+                    weekday => weekday.format(&name(&WEEKDAYS)),
+                };
+                write!(f, "{} {} {}", year, month.format(&name(&MONTHS)), day)
+            },
+            DateChecker::Expr(e) => write!(f, "{}", e),
+            DateChecker::Span { start, days } => write!(f, "{} +{}d", start, days),
         }
-        
-        // Ok(DateChecker::Expr(vec![DateExpression::W(2)]))
-        Ok(DateChecker::Expr(v))
     }
+}
 
-    pub fn check_date_range(&self, first: &date::Date, last: &date::Date) ->
-        Vec<date::Date>
-    {
-        let date_range = get_date_range(first, last);
-        let mut v: Vec<date::Date> = vec![];
-        for d in date_range {
-            if self.check_date(&d) {
-                v.push(d);
+impl DateChecker {
+    pub fn new(expr: &str) -> Result<DateChecker, ParseError> {
+        DateChecker::with_easter(expr, Easter::Western)
+    }
+
+    // with_easter is like new, but variable e in the expression counts days
+    // relative to the given Easter.
+    pub fn with_easter(expr: &str, easter: Easter) -> Result<DateChecker, ParseError> {
+        // Multi-day event: 2024 Jun 3 .. 2024 Jun 7
+        if let Some(dots) = expr.find("..") {
+            let (first, last) = (&expr[..dots], &expr[dots + 2..]);
+            let first_date = parse_date(first)?;
+            let last_date = parse_date(last).map_err(|e| e.shift(dots + 2))?;
+            if last_date < first_date {
+                let last_span = skip_whitespace(expr, dots + 2)..expr.trim_end().len();
+                return Err(ParseError::new("Range ends before it starts", last_span));
             }
+            let days = (last_date - first_date).num_days() + 1;
+            let start = DateChecker::with_easter(first, easter)?;
+            return Ok(DateChecker::Span { start: Box::new(start), days: days as u32 });
         }
-        v
+        // Multi-day event with duration: 2024 Jun 3 +5d, or w=fri +2w.
+        static DURATION: OnceLock<Regex> = OnceLock::new();
+        let re = DURATION.get_or_init(|| Regex::new(r"^(.*\S)\s+\+(\d+)([dw])\s*$").unwrap());
+        if let Some(captures) = re.captures(expr) {
+            let duration_span = captures.get(2).unwrap().start() - 1..captures.get(3).unwrap().end();
+            let bad_duration = || ParseError::new("Bad duration", duration_span.clone());
+            let count = captures[2].parse::<u32>().map_err(|_| bad_duration())?;
+            let days = if &captures[3] == "w" { count.checked_mul(7) } else { Some(count) };
+            let days = days.filter(|d| *d > 0).ok_or_else(bad_duration)?;
+            let start = DateChecker::with_easter(&captures[1], easter)?;
+            if let DateChecker::Span { .. } = start {
+                return Err(bad_duration());
+            }
+            return Ok(DateChecker::Span { start: Box::new(start), days });
+        }
+        if !expr.contains(|c| "=&|!()<>%".contains(c)) {
+            let split = words(expr);
+            if split.len() != 3 {
+                return Err(fields_error(expr, &split));
+            }
+            let year = parse_number_expression(split[0].as_str())
+                .ok_or_else(|| field_error(&split[0], "Bad year"))?;
+            let month = parse_month_expression(split[1].as_str())
+                .ok_or_else(|| field_error(&split[1], "Bad month"))?;
+            let (day, weekday) = parse_day_expression(split[2].as_str())
+                .ok_or_else(|| field_error(&split[2], "Bad day"))?;
+
+            return Ok(DateChecker::Spec { year, month, day, weekday });
+        }
+        let expression = parser::parse_expression(expr, easter)?;
+        Ok(DateChecker::Expr(expression))
     }
 
-    pub fn check_date(&self, date: &date::Date) -> bool {
+    // legacy_week_of_month returns the checker that evaluates variable a
+    // the way earlier versions of when-rs did, or None if the checker
+    // doesn't use a.
+    pub fn legacy_week_of_month(&self) -> Option<DateChecker> {
         match self {
-            DateChecker::Spec{year, month, day} => {
-                if !year.check(date.year() as u32) {
-                    return false;
-                }
+            DateChecker::Expr(e) if e.uses_variable(Variable::A) => {
+                Some(DateChecker::Expr(e.replace_variable(Variable::A, Variable::LegacyA)))
+            },
+            DateChecker::Span { start, days } => {
+                start.legacy_week_of_month()
+                    .map(|start| DateChecker::Span { start: Box::new(start), days: *days })
+            },
+            _ => None,
+        }
+    }
 
-                if !month.check(date.month()) {
-                    return false;
+    // impossible_values returns messages about values in the date pattern
+    // or expression that can never match, like * Feb 30 or w=8, each with
+    // the span of the fields or the term in text, the pattern that the
+    // checker was parsed from.
+    pub fn impossible_values(&self, text: &str) -> Vec<(String, Range<usize>)> {
+        let mut v = Vec::new();
+        match self {
+            DateChecker::Spec { month, day, .. } => {
+                static FIELD: OnceLock<Regex> = OnceLock::new();
+                let re = FIELD.get_or_init(|| Regex::new(r"\S+").unwrap());
+                let fields: Vec<Range<usize>> = re.find_iter(text).map(|m| m.range()).collect();
+                let field = |k: usize| fields.get(k).cloned().unwrap_or(0..text.len());
+                let mut months = Vec::new();
+                month.values_outside(1, 12, &mut months);
+                for m in months {
+                    v.push((format!("Month {} doesn't exist", m), field(1)));
                 }
-
-                if !day.check(date.day()) {
-                    return false;
+                let mut days = Vec::new();
+                day.values_outside(1, 31, &mut days);
+                for d in days {
+                    v.push((format!("Day {} doesn't exist", d), field(2)));
                 }
-                return true;
-            },
-            DateChecker::Expr(v) => {
-                for term in v {
-                    if !term.check(date) {
-                        return false;
+                if let (NumberCheck::Match(m), NumberCheck::Match(d)) = (month, day) {
+                    // 2000 is a leap year.
+                    if (1..=12).contains(m) && (1..=31).contains(d) &&
+                        *d > date::days_in_month(2000, *m)
+                    {
+                        let name = MONTHS[*m as usize - 1].1;
+                        let message = format!("{}{} {} doesn't exist",
+                                              name[..1].to_uppercase(), &name[1..3], d);
+                        v.push((message, field(1).start..field(2).end));
                     }
                 }
-                return true;
-            }
+            },
+            DateChecker::Expr(e) => {
+                let mut terms = Vec::new();
+                e.impossible_terms(&mut terms);
+                let mut from = 0;
+                for (message, var, n) in terms {
+                    let span = term_span(text, from, var, n).unwrap_or(0..text.len());
+                    from = span.end.min(text.len());
+                    v.push((message, span));
+                }
+            },
+            DateChecker::Span { start, .. } => v = start.impossible_values(text),
         }
+        v
+    }
 
-        
+    // mentioned_years returns years that the date pattern or expression
+    // refers to, like 2024 in 2024 Jun 3 or in y=2024. If the checker
+    // matches any dates, it probably matches some in those years.
+    pub fn mentioned_years(&self) -> Vec<i32> {
+        match self {
+            DateChecker::Spec { year, .. } => {
+                year.values(28).into_iter().filter_map(|y| i32::try_from(y).ok()).collect()
+            },
+            DateChecker::Expr(e) => {
+                let mut v = Vec::new();
+                e.mentioned_years(&mut v);
+                v
+            },
+            DateChecker::Span { start, .. } => start.mentioned_years(),
+        }
     }
-}
 
-// TODO: parse date expression (2020 Dec 27).
-//       There are two types of expressions:
-//       1. - Date pattern, such as * Feb 14
-//       2. - Conjunction of terms, such as
-//            m=jan & w=mon & a=3
+    // span_day returns the day of the multi-day event on the date and the
+    // number of days of the event, e.g. (2, 5) for the second day of a
+    // five-day event. It returns None if the checker isn't a multi-day
+    // event or the date isn't in it. If events overlap, the day is counted
+    // from the latest start.
+    pub fn span_day(&self, date: &date::Date) -> Option<(u32, u32)> {
+        match self {
+            DateChecker::Span { start, days } => {
+                (0..*days)
+                    .find(|k| {
+                        date.checked_sub_signed(Duration::days(*k as i64))
+                            .map(|d| start.check_date(&d))
+                            .unwrap_or(false)
+                    })
+                    .map(|k| (k + 1, *days))
+            },
+            _ => None,
+        }
+    }
 
-pub fn parse_date(s: &str) -> Option<date::Date> {
-    let re = Regex::new(r"\s+").unwrap();
-    let split: Vec<&str> = re.split(s).collect();
-    if split.len() != 3 {
-        return None;
+    pub fn check_date_range(&self, first: &date::Date, last: &date::Date) ->
+        Vec<date::Date>
+    {
+        self.occurrences(first).until(*last).collect()
     }
-    
-    let year = split[0].parse::<i32>();
-    if year.is_err() {
-        return None;
+
+    // occurrences returns the dates that match, starting with from and going
+    // forward, e.g. checker.occurrences(&today).take(3) are the next three.
+    // It stops if nothing matches in 400 years after from and the years in
+    // the pattern, or at the date given to until.
+    pub fn occurrences(&self, from: &date::Date) -> Dates<'_> {
+        Dates::new(self, from, true)
     }
-    let year = year.unwrap();
 
-    let month = parse_month(split[1]);
-    if month.is_none() {
-        return None;
+    // occurrences_backward is like occurrences, but goes backward from
+    // from, starting with the most recent date.
+    pub fn occurrences_backward(&self, from: &date::Date) -> Dates<'_> {
+        Dates::new(self, from, false)
     }
-    let month = month.unwrap();
 
-    let day = split[2].parse::<u32>();
-    if day.is_err() {
-        return None;
+    pub fn check_date(&self, date: &date::Date) -> bool {
+        match self {
+            DateChecker::Spec{year, month, day, weekday} => {
+                // Years in date patterns are from 0 on, so years before it
+                // don't match even open ranges like 2020/1.
+                u32::try_from(date.year()).is_ok_and(|y| year.check(y)) &&
+                    month.check(date.month()) &&
+                    day.check(date.day()) &&
+                    weekday.check(date.weekday().number_from_monday())
+            },
+            DateChecker::Expr(e) => e.check(date),
+            DateChecker::Span { .. } => self.span_day(date).is_some(),
+        }
+    }
+}
+
+// There are two types of date expressions:
+// 1. - Date pattern, such as * Feb 14
+// 2. - Expression over variables, such as
+//      m=jan & w=1 & a=3, (m=dec & d=24) | (m=dec & d=31) or y%4=0 & d>=15
+// Either can be made into a multi-day event with a duration, like
+// * Dec 24 +3d, or two dates can make a range, like 2024 Jun 3 .. 2024 Jun 7.
+
+// parse_date parses a date like 2021 Jan 9. Spans of errors are in s.
+pub fn parse_date(s: &str) -> Result<date::Date, ParseError> {
+    let split = words(s);
+    if split.len() != 3 {
+        return Err(fields_error(s, &split));
     }
-    let day = day.unwrap();
-    
-    Some(date::new_date(year, month as u32, day))
+
+    let year = split[0].as_str().parse::<i32>()
+        .map_err(|_| field_error(&split[0], "Bad year"))?;
+    let month = parse_month(split[1].as_str())
+        .ok_or_else(|| field_error(&split[1], "Bad month"))?;
+    let day = split[2].as_str().parse::<u32>()
+        .map_err(|_| field_error(&split[2], "Bad day"))?;
+
+    date::new_date_opt(year, month as u32, day).ok_or_else(|| {
+        let span = split[0].start()..split[2].end();
+        let message = format!("{} doesn't exist", &s[span.clone()]);
+        ParseError::new(&message, span)
+    })
+}
+
+// Calendar item on a date, like "2021 Sep 27, 09:30-10:00, Standup".
+#[derive(PartialEq, Debug, Clone)]
+pub struct Occurrence {
+    pub date: date::Date,
+    pub time: Option<date::TimeRange>,
+    pub description: String,
+    pub span_day: Option<(u32, u32)>, // day of multi-day event and its days
 }
 
-pub fn sort_dates(dates: Vec<(date::Date, String)>) -> Vec<(date::Date, String)> {
+// sort_dates sorts occurrences by date and then by time. On each date,
+// occurrences without time come first; otherwise the order is kept.
+pub fn sort_dates(dates: Vec<Occurrence>) -> Vec<Occurrence> {
     let mut sorted_dates = dates;
     sorted_dates.sort_by(|x, y| {
-        x.0.cmp(&y.0)
+        (x.date, x.time).cmp(&(y.date, y.time))
     });
     sorted_dates
 }
@@ -324,9 +1073,18 @@ mod tests {
         assert_eq!(1, 1);
     }
 
+    // Utility function used just for test; builds term like w=3.
+    fn new_term(variable: Variable, value: i64) -> DateExpression {
+        DateExpression::Binary(
+            BinaryOperator::Equal,
+            Box::new(DateExpression::Variable(variable)),
+            Box::new(DateExpression::Number(value)),
+        )
+    }
+
     // Utility function used just for test
     fn new_date(year: i32, month: u32, day: u32) -> date::Date {
-        NaiveDate::from_ymd(year, month, day)
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
     }
 
     #[test]
@@ -383,6 +1141,242 @@ mod tests {
         assert!(!checker.check_date(&date));
     }
 
+    #[test]
+    fn boolean_expression_check_test() {
+        let checker = DateChecker::new("(m=dec & d=24) | (m=dec & d=31)").unwrap();
+        assert!(checker.check_date(&new_date(2021, 12, 24)));
+        assert!(checker.check_date(&new_date(2021, 12, 31)));
+        assert!(!checker.check_date(&new_date(2021, 12, 25)));
+        assert!(!checker.check_date(&new_date(2021, 11, 24)));
+
+        // 2021 Sep 25 is Saturday, 2021 Sep 27 is Monday.
+        let checker = DateChecker::new("w=6 | w=7").unwrap();
+        assert!(checker.check_date(&new_date(2021, 9, 25)));
+        assert!(!checker.check_date(&new_date(2021, 9, 27)));
+
+        let checker = DateChecker::new("!(w=6 | w=7)").unwrap();
+        assert!(!checker.check_date(&new_date(2021, 9, 25)));
+        assert!(checker.check_date(&new_date(2021, 9, 27)));
+
+        let checker = DateChecker::new("m=sep & !w=6").unwrap();
+        assert!(!checker.check_date(&new_date(2021, 9, 25)));
+        assert!(checker.check_date(&new_date(2021, 9, 26)));
+    }
+
+    #[test]
+    fn arithmetic_expression_check_test() {
+        // Second half of each month
+        let checker = DateChecker::new("d>=15").unwrap();
+        assert!(checker.check_date(&new_date(2021, 2, 15)));
+        assert!(checker.check_date(&new_date(2021, 2, 28)));
+        assert!(!checker.check_date(&new_date(2021, 2, 14)));
+
+        // Every fourth year
+        let checker = DateChecker::new("y%4=0 & m=feb & d=29").unwrap();
+        assert!(checker.check_date(&new_date(2024, 2, 29)));
+        assert!(!checker.check_date(&new_date(2024, 2, 28)));
+
+        let checker = DateChecker::new("(z%14)=0").unwrap();
+        assert!(checker.check_date(&new_date(2021, 1, 14)));
+        assert!(checker.check_date(&new_date(2021, 1, 28)));
+        assert!(!checker.check_date(&new_date(2021, 1, 15)));
+
+        // 2021 Sep 27 is Monday.
+        let checker = DateChecker::new("w!=1").unwrap();
+        assert!(!checker.check_date(&new_date(2021, 9, 27)));
+        assert!(checker.check_date(&new_date(2021, 9, 28)));
+
+        let checker = DateChecker::new("d<3 | d>30").unwrap();
+        assert!(checker.check_date(&new_date(2021, 1, 2)));
+        assert!(!checker.check_date(&new_date(2021, 1, 3)));
+        assert!(checker.check_date(&new_date(2021, 1, 31)));
+
+        // + and - bind tighter than comparisons, % tighter than + and -.
+        let checker = DateChecker::new("d-1=m+z%2").unwrap();
+        assert!(checker.check_date(&new_date(2021, 1, 3)));
+        assert!(!checker.check_date(&new_date(2021, 1, 4)));
+        let checker = DateChecker::new("-d<=-31").unwrap();
+        assert!(checker.check_date(&new_date(2021, 1, 31)));
+        assert!(!checker.check_date(&new_date(2021, 1, 30)));
+    }
+
+    #[test]
+    fn easter_expression_check_test() {
+        // Western Easter 2024 is on March 31, Orthodox on May 5.
+        let checker = DateChecker::new("e=-2").unwrap();
+        assert!(checker.check_date(&new_date(2024, 3, 29)));
+        assert!(!checker.check_date(&new_date(2024, 5, 3)));
+
+        let checker = DateChecker::with_easter("e=-2", Easter::Orthodox).unwrap();
+        assert!(!checker.check_date(&new_date(2024, 3, 29)));
+        assert!(checker.check_date(&new_date(2024, 5, 3)));
+
+        // Pentecost
+        let checker = DateChecker::new("e=49").unwrap();
+        assert!(checker.check_date(&new_date(2024, 5, 19)));
+
+        // Easter Monday
+        let checker = DateChecker::new("e=1 & w=1").unwrap();
+        assert!(checker.check_date(&new_date(2025, 4, 21)));
+    }
+
+    #[test]
+    fn julian_day_expression_check_test() {
+        assert_eq!(Variable::J.value(&new_date(1858, 11, 17)), 0);
+        assert_eq!(Variable::J.value(&new_date(2021, 1, 1)), 59215);
+        assert_eq!(Variable::J.value(&new_date(1800, 1, 1)), -21504);
+
+        // Every 14 days, starting with 2021 Jan 4 (59218 % 14 = 12).
+        let checker = DateChecker::new("j%14=12").unwrap();
+        let dates = checker.check_date_range(&new_date(2021, 1, 1), &new_date(2021, 2, 28));
+        assert_eq!(dates, vec![
+            new_date(2021, 1, 4),
+            new_date(2021, 1, 18),
+            new_date(2021, 2, 1),
+            new_date(2021, 2, 15),
+        ]);
+
+        let checker = DateChecker::new("j>=59215 & j<59217").unwrap();
+        assert!(!checker.check_date(&new_date(2020, 12, 31)));
+        assert!(checker.check_date(&new_date(2021, 1, 2)));
+        assert!(!checker.check_date(&new_date(2021, 1, 3)));
+    }
+
+    #[test]
+    fn week_from_end_of_month_check_test() {
+        assert_eq!(Variable::B.value(&new_date(2021, 2, 28)), 1);
+        assert_eq!(Variable::B.value(&new_date(2021, 2, 22)), 1);
+        assert_eq!(Variable::B.value(&new_date(2021, 2, 21)), 2);
+        assert_eq!(Variable::B.value(&new_date(2024, 2, 22)), 2);
+        assert_eq!(Variable::B.value(&new_date(2021, 1, 1)), 5);
+
+        // Memorial Day, the last Monday in May
+        let checker = DateChecker::new("m=may & w=1 & b=1").unwrap();
+        let dates = checker.check_date_range(&new_date(2021, 1, 1), &new_date(2022, 12, 31));
+        assert_eq!(dates, vec![new_date(2021, 5, 31), new_date(2022, 5, 30)]);
+    }
+
+    #[test]
+    fn week_of_month_check_test() {
+        let checker = DateChecker::new("a=1").unwrap();
+        assert!(checker.check_date(&new_date(2021, 3, 1)));
+        assert!(checker.check_date(&new_date(2021, 3, 7)));
+        assert!(!checker.check_date(&new_date(2021, 3, 8)));
+
+        let checker = DateChecker::new("a=2").unwrap();
+        assert!(checker.check_date(&new_date(2021, 3, 14)));
+        assert!(!checker.check_date(&new_date(2021, 3, 15)));
+
+        // US Thanksgiving, the fourth Thursday in November
+        let checker = DateChecker::new("m=nov & w=4 & a=4").unwrap();
+        let dates = checker.check_date_range(&new_date(2024, 1, 1), &new_date(2024, 12, 31));
+        assert_eq!(dates, vec![new_date(2024, 11, 28)]);
+
+        // Before, a was 4 on 2024 Nov 21 and 5 on 2024 Nov 28.
+        let legacy = checker.legacy_week_of_month().unwrap();
+        let dates = legacy.check_date_range(&new_date(2024, 1, 1), &new_date(2024, 12, 31));
+        assert_eq!(dates, vec![new_date(2024, 11, 21)]);
+
+        assert!(DateChecker::new("m=nov & w=4").unwrap().legacy_week_of_month().is_none());
+        assert!(DateChecker::new("* Nov 1").unwrap().legacy_week_of_month().is_none());
+    }
+
+    #[test]
+    fn span_check_test() {
+        let checker = DateChecker::new("2024 Jun 3 .. 2024 Jun 7").unwrap();
+        let dates = checker.check_date_range(&new_date(2024, 6, 1), &new_date(2024, 6, 30));
+        assert_eq!(dates.len(), 5);
+        assert_eq!(dates[0], new_date(2024, 6, 3));
+        assert_eq!(dates[4], new_date(2024, 6, 7));
+        assert_eq!(checker.span_day(&new_date(2024, 6, 4)), Some((2, 5)));
+        assert_eq!(checker.span_day(&new_date(2024, 6, 8)), None);
+
+        let checker = DateChecker::new("2024 Dec 30 .. 2025 jan 2").unwrap();
+        assert_eq!(checker.span_day(&new_date(2025, 1, 1)), Some((3, 4)));
+
+        let checker = DateChecker::new("2024 Jun 3 +5d").unwrap();
+        let dates = checker.check_date_range(&new_date(2024, 6, 1), &new_date(2024, 6, 30));
+        assert_eq!(dates.len(), 5);
+        assert_eq!(dates[4], new_date(2024, 6, 7));
+
+        // Every year, from Christmas Eve to Boxing Day
+        let checker = DateChecker::new("* dec 24 +3d").unwrap();
+        assert_eq!(checker.span_day(&new_date(2021, 12, 26)), Some((3, 3)));
+        assert!(!checker.check_date(&new_date(2021, 12, 27)));
+
+        let checker = DateChecker::new("m=jul & w=sat & a=1 +1w").unwrap();
+        assert_eq!(checker.span_day(&new_date(2021, 7, 3)), Some((1, 7)));
+        assert_eq!(checker.span_day(&new_date(2021, 7, 9)), Some((7, 7)));
+        assert!(!checker.check_date(&new_date(2021, 7, 10)));
+
+        assert!(DateChecker::new("* * 1").unwrap().span_day(&new_date(2021, 7, 1)).is_none());
+        assert!(DateChecker::new("2024 Jun 7 .. 2024 Jun 3").is_err());
+        assert!(DateChecker::new("* Jun 7 .. 2024 Jun 9").is_err());
+        assert!(DateChecker::new("2024 Jun 7 +0d").is_err());
+        assert!(DateChecker::new("2024 Jun 7 +2d +2d").is_err());
+    }
+
+    #[test]
+    fn parse_error_span_test() {
+        let span = |expr| DateChecker::new(expr).err().unwrap().span;
+        assert_eq!(span("* Jly 4"), 2..5);
+        assert_eq!(DateChecker::new("* jly 4").err().unwrap().message, "Bad month");
+        assert_eq!(DateChecker::new("* jan,ju 4").err().unwrap().message,
+                   "Ambiguous month ju: could be june or july");
+        assert_eq!(DateChecker::new("* * t").err().unwrap().message,
+                   "Ambiguous weekday t: could be tuesday or thursday");
+        assert_eq!(DateChecker::new("m=ma").err().unwrap().message,
+                   "Ambiguous month ma: could be march or may");
+        assert_eq!(span("2024 Jun 31x"), 9..12);
+        assert_eq!(span("* Jun"), 5..5);
+        assert_eq!(span("* Jun 1 2 3 "), 8..11);
+        assert_eq!(span("2024 Jun 3 .. 2024 Jux 7"), 19..22);
+        assert_eq!(span("2024 Jun 3 .. 2024 Jun 1"), 14..24);
+        assert_eq!(span("2024 Jun 3 +0d"), 11..14);
+        assert_eq!(span("m=jan & x=1"), 8..9);
+        assert_eq!(DateChecker::new("m=jan & x=1").err().unwrap().message,
+                   "Unknown variable x");
+    }
+
+    #[test]
+    fn impossible_values_test() {
+        let messages = |expr| DateChecker::new(expr).unwrap().impossible_values(expr);
+        let message = |message: &str, span: Range<usize>| (message.to_string(), span);
+        assert_eq!(messages("* Feb 30"), vec![message("Feb 30 doesn't exist", 2..8)]);
+        assert_eq!(messages("*  Apr 31"), vec![message("Apr 31 doesn't exist", 3..9)]);
+        assert_eq!(messages("* 13 1"), vec![message("Month 13 doesn't exist", 2..4)]);
+        assert_eq!(messages("* * 30-32"), vec![message("Day 32 doesn't exist", 4..9)]);
+        assert_eq!(messages("w=8"), vec![message("w=8 is never true: w is from 1 to 7", 0..3)]);
+        assert_eq!(messages("m=13 | 367 = z"), vec![
+            message("m=13 is never true: m is from 1 to 12", 0..4),
+            message("z=367 is never true: z is from 1 to 366", 7..14),
+        ]);
+        assert_eq!(messages("w=8 | w=8"), vec![
+            message("w=8 is never true: w is from 1 to 7", 0..3),
+            message("w=8 is never true: w is from 1 to 7", 6..9),
+        ]);
+        assert_eq!(messages("d=1 & w=8 +2d"), vec![
+            message("w=8 is never true: w is from 1 to 7", 6..9),
+        ]);
+
+        assert!(messages("* Feb 29").is_empty());
+        assert!(messages("* * */5").is_empty());
+        assert!(messages("m=jan & z=200").is_empty());
+        assert!(messages("y=2024 & w<=8").is_empty());
+    }
+
+    #[test]
+    fn mentioned_years_test() {
+        let years = |expr| DateChecker::new(expr).unwrap().mentioned_years();
+        assert_eq!(years("2024 Jun 3"), vec![2024]);
+        assert_eq!(years("2020-2030/4 feb 29"), vec![2020, 2024, 2028]);
+        assert_eq!(years("2024,1999 * 1"), vec![2024, 1999]);
+        assert!(years("* Jun 3").is_empty());
+        assert_eq!(years("2024 Jun 3 .. 2024 Jun 7"), vec![2024]);
+        assert_eq!(years("m=1 & (y>=2030 | 1999=y)"), vec![2030, 1999]);
+        assert_eq!(years("j=59215"), vec![2021]);
+    }
+
     #[test]
     fn parsing_date_expression() {
         assert_eq!(1, 1);
@@ -407,19 +1401,17 @@ mod tests {
     #[test]
     fn parsing_test_variables() {
         let checker = DateChecker::new("w=2");
-        assert!(!checker.is_err());
+        assert!(checker.is_ok());
         let checker = checker.unwrap();
         match checker {
-            DateChecker::Expr(v) => {
-                assert_eq!(v.len(), 1);
-                // TODO: check that v[0] is DateExpression::W(2).
-                assert_eq!(v[0], DateExpression::W(2));
+            DateChecker::Expr(e) => {
+                assert_eq!(e, new_term(Variable::W, 2));
             },
-            _ => assert!(false),
+            _ => panic!("expected expression"),
         }
 
         let checker = DateChecker::new("m=feb");
-        assert!(!checker.is_err());
+        assert!(checker.is_ok());
     }
 
     #[test]
@@ -435,11 +1427,71 @@ mod tests {
     fn creating_date_range() {
         let date1 = new_date(2020, 12, 28);
         let date2 = new_date(2021, 1, 3);
-        let r = get_date_range(&date1, &date2);
+        let checker = DateChecker::new("* * *").unwrap();
+        let r: Vec<date::Date> = checker.occurrences(&date1).until(date2).collect();
         eprintln!("r is {:?}", r);
         assert_eq!(r.len(), 7);
     }
 
+    #[test]
+    fn occurrences_same_as_scanning_test() {
+        let first = new_date(2023, 11, 20);
+        let last = new_date(2025, 3, 10);
+        let patterns = [
+            "* Dec 25", "* feb 29", "2024 jan-mar 1", "* * fri", "* jun,aug */10",
+            "2020-2030/4 * 31", "m=dec & (d=24 | d=31)", "d>=15 & m<feb", "z=60 | z=366",
+            "y=2024 & w=mon & a=2", "!(m=dec) & d=1", "m=jan & z=200", "e=-2",
+            "d-1=m+z%2", "(m=2)+(d=3)=2", "j%14=3", "24<=d & d<=26 & 12=m",
+            "2024 Jun 3 .. 2024 Jun 7", "w=fri +3d", "y>2024 | m=nov & d>28",
+            "a>=4 & d<30 & w<=fri", "w=8 | m=7 & a=5", "* * sat-sun",
+        ];
+        for pattern in &patterns {
+            let checker = DateChecker::new(pattern).unwrap();
+            let mut scanned = Vec::new();
+            let mut d = first;
+            while d <= last {
+                if checker.check_date(&d) {
+                    scanned.push(d);
+                }
+                d = d.succ_opt().unwrap();
+            }
+            let forward: Vec<date::Date> = checker.occurrences(&first).until(last).collect();
+            assert_eq!(forward, scanned, "{}", pattern);
+            let mut backward: Vec<date::Date> = checker.occurrences_backward(&last)
+                .until(first).collect();
+            backward.reverse();
+            assert_eq!(backward, scanned, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn occurrences_test() {
+        let from = new_date(2024, 6, 1);
+        let checker = DateChecker::new("w=fri").unwrap();
+        let next: Vec<date::Date> = checker.occurrences(&from).take(3).collect();
+        assert_eq!(next, vec![new_date(2024, 6, 7), new_date(2024, 6, 14), new_date(2024, 6, 21)]);
+        let previous: Vec<date::Date> = checker.occurrences_backward(&from).take(2).collect();
+        assert_eq!(previous, vec![new_date(2024, 5, 31), new_date(2024, 5, 24)]);
+
+        // The first date counts in both directions.
+        let checker = DateChecker::new("* Jun 1").unwrap();
+        assert_eq!(checker.occurrences(&from).next(), Some(from));
+        assert_eq!(checker.occurrences_backward(&from).nth(1), Some(new_date(2023, 6, 1)));
+
+        // Far future, but not forever.
+        let checker = DateChecker::new("2100 Jan 1").unwrap();
+        assert_eq!(checker.occurrences(&from).next(), Some(new_date(2100, 1, 1)));
+        assert_eq!(checker.occurrences(&from).nth(1), None);
+        assert_eq!(checker.occurrences_backward(&from).next(), None);
+        let checker = DateChecker::new("* Feb 30").unwrap();
+        assert_eq!(checker.occurrences(&from).next(), None);
+
+        let checker = DateChecker::new("w=fri").unwrap();
+        let until: Vec<date::Date> = checker.occurrences_backward(&from)
+            .until(new_date(2024, 5, 24)).collect();
+        assert_eq!(until, vec![new_date(2024, 5, 31), new_date(2024, 5, 24)]);
+    }
+
     #[test]
     fn check_date_range_test() {
         let date1 = new_date(2020, 12, 28);
@@ -470,21 +1522,131 @@ mod tests {
         assert!(month.is_none());
     }
 
+    #[test]
+    fn parse_weekday_test() {
+        assert_eq!(parse_weekday("mon"), Some(1));
+        assert_eq!(parse_weekday("Fri"), Some(5));
+        assert_eq!(parse_weekday("SUNDAY"), Some(7));
+        assert_eq!(parse_weekday("th"), Some(4));
+
+        // Ambiguous: Tuesday or Thursday, Saturday or Sunday.
+        assert_eq!(parse_weekday("t"), None);
+        assert_eq!(parse_weekday("s"), None);
+        assert_eq!(parse_weekday("jan"), None);
+    }
+
+    #[test]
+    fn weekday_check_test() {
+        // 2021 Sep 24 is Friday.
+        let checker = DateChecker::new("w=fri").unwrap();
+        assert!(checker.check_date(&new_date(2021, 9, 24)));
+        assert!(!checker.check_date(&new_date(2021, 9, 25)));
+
+        let checker = DateChecker::new("w=Sa | w=su").unwrap();
+        assert!(checker.check_date(&new_date(2021, 9, 25)));
+        assert!(checker.check_date(&new_date(2021, 9, 26)));
+        assert!(!checker.check_date(&new_date(2021, 9, 27)));
+
+        let checker = DateChecker::new("* * fri").unwrap();
+        assert!(checker.check_date(&new_date(2021, 9, 24)));
+        assert!(!checker.check_date(&new_date(2021, 9, 23)));
+
+        let checker = DateChecker::new("2021 sep Friday").unwrap();
+        assert!(checker.check_date(&new_date(2021, 9, 24)));
+        assert!(!checker.check_date(&new_date(2021, 10, 1)));
+
+        assert!(DateChecker::new("* * t").is_err());
+        assert!(DateChecker::new("w=t").is_err());
+    }
+
+    #[test]
+    fn parse_field_test() {
+        assert_eq!(parse_number_expression("*"), Some(NumberCheck::Any));
+        assert_eq!(parse_number_expression("15"), Some(NumberCheck::Match(15)));
+        assert_eq!(parse_number_expression("1-7"),
+                   Some(NumberCheck::Range { first: 1, last: 7, step: 1 }));
+        assert_eq!(parse_month_expression("*/3"),
+                   Some(NumberCheck::Range { first: 1, last: 12, step: 3 }));
+        assert_eq!(parse_month_expression("jun,aug"),
+                   Some(NumberCheck::List(vec![NumberCheck::Match(6), NumberCheck::Match(8)])));
+        assert_eq!(parse_month_expression("2/4"),
+                   Some(NumberCheck::Range { first: 2, last: 12, step: 4 }));
+
+        assert_eq!(parse_number_expression("7-1"), None);
+        assert_eq!(parse_number_expression("*/0"), None);
+        assert_eq!(parse_number_expression("1,"), None);
+        assert_eq!(parse_number_expression("1-"), None);
+        assert_eq!(parse_month_expression("ju,aug"), None);
+    }
+
+    #[test]
+    fn date_pattern_set_check_test() {
+        let checker = DateChecker::new("2024 jan-mar 1").unwrap();
+        assert!(checker.check_date(&new_date(2024, 2, 1)));
+        assert!(checker.check_date(&new_date(2024, 3, 1)));
+        assert!(!checker.check_date(&new_date(2024, 4, 1)));
+        assert!(!checker.check_date(&new_date(2024, 2, 2)));
+
+        let checker = DateChecker::new("* jun,aug 15").unwrap();
+        assert!(checker.check_date(&new_date(2021, 6, 15)));
+        assert!(!checker.check_date(&new_date(2021, 7, 15)));
+        assert!(checker.check_date(&new_date(2021, 8, 15)));
+
+        let checker = DateChecker::new("* * 1-7").unwrap();
+        assert!(checker.check_date(&new_date(2021, 6, 7)));
+        assert!(!checker.check_date(&new_date(2021, 6, 8)));
+
+        let checker = DateChecker::new("* * */5").unwrap();
+        let dates = checker.check_date_range(&new_date(2021, 1, 1), &new_date(2021, 1, 31));
+        let days: Vec<u32> = dates.iter().map(|d| d.day()).collect();
+        assert_eq!(days, vec![1, 6, 11, 16, 21, 26, 31]);
+
+        let checker = DateChecker::new("2020-2030/4 feb 29").unwrap();
+        assert!(checker.check_date(&new_date(2024, 2, 29)));
+        assert!(checker.check_date(&new_date(2028, 2, 29)));
+
+        let checker = DateChecker::new("2020/1 Jan 1").unwrap();
+        assert!(checker.check_date(&new_date(2030, 1, 1)));
+        assert!(!checker.check_date(&new_date(-5, 1, 1)));
+        assert_eq!(checker.occurrences_backward(&new_date(2022, 6, 1)).last(),
+                   Some(new_date(2020, 1, 1)));
+
+        // 2021 Sep 24 is Friday.
+        let checker = DateChecker::new("* * mon-fri").unwrap();
+        assert!(checker.check_date(&new_date(2021, 9, 24)));
+        assert!(!checker.check_date(&new_date(2021, 9, 25)));
+        let checker = DateChecker::new("* * sat,sun").unwrap();
+        assert!(checker.check_date(&new_date(2021, 9, 25)));
+        assert!(!checker.check_date(&new_date(2021, 9, 24)));
+
+        assert!(DateChecker::new("* * 1,fri").is_err());
+        assert!(DateChecker::new("* mar-jan 1").is_err());
+    }
+
     #[test]
     fn parse_date_test() {
         let date = parse_date("2021 Jan 9");
-        assert!(date.is_some());
+        assert!(date.is_ok());
         // TODO:
         let date = date.unwrap();
         assert_eq!(date.year(), 2021);
         assert_eq!(date.month(), 1);
         assert_eq!(date.day(), 9);
+
+        assert_eq!(parse_date(" 2021  Jan 9 "), Ok(date));
+        assert_eq!(parse_date("2021 ju 9"),
+                   Err(ParseError::new("Ambiguous month ju: could be june or july", 5..7)));
+        assert_eq!(parse_date("* Jan 9"), Err(ParseError::new("Bad year", 0..1)));
+        assert_eq!(parse_date("2021 Jan"),
+                   Err(ParseError::new("Expected year, month and day", 8..8)));
+        assert_eq!(parse_date("2023 Feb  29"),
+                   Err(ParseError::new("2023 Feb  29 doesn't exist", 0..12)));
     }
 
     #[test]
     fn check_date_term_test() {
         // Test w
-        let term = DateExpression::W(3); // Wednesday
+        let term = new_term(Variable::W, 3); // Wednesday
         let date = parse_date("2038 Jan 20").unwrap();
         assert!(term.check(&date));
 
@@ -493,7 +1655,7 @@ mod tests {
         assert!(!term.check(&date));
 
         // Test m
-        let term = DateExpression::M(7); // July
+        let term = new_term(Variable::M, 7); // July
         let date = parse_date("2021 July 11").unwrap();
         assert!(term.check(&date));
 
@@ -502,7 +1664,7 @@ mod tests {
         assert!(!term.check(&date));
 
         // Test d
-        let term = DateExpression::D(17);
+        let term = new_term(Variable::D, 17);
         let date = parse_date("2021 Feb 17").unwrap();
         assert!(term.check(&date));
 
@@ -511,7 +1673,7 @@ mod tests {
         assert!(!term.check(&date));
 
         // Test y
-        let term = DateExpression::Y(2001);
+        let term = new_term(Variable::Y, 2001);
         let date = parse_date("2001 january 1").unwrap();
         assert!(term.check(&date));
 
@@ -520,7 +1682,7 @@ mod tests {
         assert!(!term.check(&date));
 
         // Test a
-        let term = DateExpression::A(2);
+        let term = new_term(Variable::A, 2);
         let date = parse_date("2021 Feb 8").unwrap();
         assert!(term.check(&date));
 
@@ -529,7 +1691,7 @@ mod tests {
         assert!(!term.check(&date));
 
         // Test z
-        let term = DateExpression::Z(32);
+        let term = new_term(Variable::Z, 32);
         let date = parse_date("2021 Feb 1").unwrap();
         assert!(term.check(&date));
 
@@ -540,14 +1702,51 @@ mod tests {
 
     #[test]
     fn sort_dates_test() {
-        let mut dates: Vec<(date::Date, String)> = Vec::new();
-        dates.push((new_date(2020, 12, 28), "first".to_string()));
-        dates.push((new_date(2020, 11, 20), "second".to_string()));
-        dates.push((new_date(2020, 12, 10), "third".to_string()));
+        let occurrence = |date, time: Option<&str>, description: &str| Occurrence {
+            date,
+            time: time.map(|t| date::TimeRange::parse(t).unwrap()),
+            description: description.to_string(),
+            span_day: None,
+        };
+        let dates = vec![
+            occurrence(new_date(2020, 12, 28), None, "first"),
+            occurrence(new_date(2020, 11, 20), None, "second"),
+            occurrence(new_date(2020, 12, 10), None, "third"),
+        ];
         let dates = sort_dates(dates);
         assert_eq!(dates.len(), 3);
-        assert_eq!(dates[0], (new_date(2020, 11, 20), "second".to_string()));
-        assert_eq!(dates[1], (new_date(2020, 12, 10), "third".to_string()));
-        assert_eq!(dates[2], (new_date(2020, 12, 28), "first".to_string()));
+        assert_eq!(dates[0], occurrence(new_date(2020, 11, 20), None, "second"));
+        assert_eq!(dates[1], occurrence(new_date(2020, 12, 10), None, "third"));
+        assert_eq!(dates[2], occurrence(new_date(2020, 12, 28), None, "first"));
+
+        let dates = vec![
+            occurrence(new_date(2020, 12, 28), Some("14:00-15:00"), "review"),
+            occurrence(new_date(2020, 12, 28), Some("09:30"), "standup"),
+            occurrence(new_date(2020, 12, 28), None, "birthday"),
+            occurrence(new_date(2020, 12, 27), Some("18:00"), "dinner"),
+            occurrence(new_date(2020, 12, 28), None, "holiday"),
+        ];
+        let descriptions: Vec<String> = sort_dates(dates).into_iter()
+            .map(|o| o.description)
+            .collect();
+        assert_eq!(descriptions, vec!["dinner", "birthday", "holiday", "standup", "review"]);
+    }
+
+    #[test]
+    fn display_test() {
+        let display = |expr| DateChecker::new(expr).unwrap().to_string();
+        assert_eq!(display("(m=12 & d>=24) | !w=6"), "m=12 & d>=24 | !w=6");
+        assert_eq!(display("m=12 & (d=24 | d=31)"), "m=12 & (d=24 | d=31)");
+        assert_eq!(display("(j-1)%14 = 0"), "(j-1)%14=0");
+        assert_eq!(display("* Dec 25"), "* dec 25");
+        assert_eq!(display("2020-2030/2 Jun,Aug */10"), "2020-2030/2 jun,aug 1-31/10");
+        assert_eq!(display("2024 Jun 3 +5d"), "2024 jun 3 +5d");
+        assert_eq!(display("2020/4 Feb 29"), "2020/4 feb 29");
+        assert_eq!(display("2020/1,1990 * 1"), "2020/1,1990 * 1");
+        for expr in &["(m=12 & d>=24) | !w=6", "-(d - 3) < 2", "* * mon-fri", "z>100 & !(a=1 | a=2)",
+                      "2020/4 Feb 29", "2020/1 Jun 1", "*/4 Jan 1"] {
+            let checker = DateChecker::new(expr).unwrap();
+            assert_eq!(DateChecker::new(&checker.to_string()).unwrap(), checker);
+        }
     }
 }
@@ -0,0 +1,106 @@
+use std::fmt;
+use std::ops::Range;
+
+// Problem found on a line of a calendar file, like a date pattern that
+// can't be parsed.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line_number: usize,
+    pub column: usize, // in characters, starting with 1
+    pub span: Range<usize>, // in bytes of line
+    pub message: String,
+    pub line: String,
+}
+
+impl Diagnostic {
+    // new creates diagnostic for the problem at span of bytes in line.
+    pub fn new(file: &str, line_number: usize, line: &str, span: Range<usize>,
+               message: &str) -> Diagnostic
+    {
+        let span = clamp_span(line, span);
+        let column = line[..span.start].chars().count() + 1;
+        Diagnostic {
+            file: file.to_string(),
+            line_number,
+            column,
+            span,
+            message: message.to_string(),
+            line: line.to_string(),
+        }
+    }
+
+    // format formats the diagnostic with the line and carets under the
+    // span, like this:
+    //
+    //   calendar:3:3: warning: Bad month
+    //       * Jly 4, Independence Day
+    //         ^^^
+    pub fn format(&self, severity: &str) -> String {
+        format!("{}:{}:{}: {}: {}\n    {}\n    {}",
+                self.file, self.line_number, self.column, severity, self.message,
+                self.line, underline(&self.line, self.span.clone()))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format("warning"))
+    }
+}
+
+// clamp_span returns span limited to line and to character boundaries.
+fn clamp_span(line: &str, span: Range<usize>) -> Range<usize> {
+    let boundary = |mut i: usize| {
+        i = i.min(line.len());
+        while !line.is_char_boundary(i) {
+            i -= 1;
+        }
+        i
+    };
+    let start = boundary(span.start);
+    start..boundary(span.end).max(start)
+}
+
+// underline returns the line of carets that goes under span of bytes in
+// line. An empty span, like the end of line where something is missing,
+// gets a single caret.
+pub fn underline(line: &str, span: Range<usize>) -> String {
+    let span = clamp_span(line, span);
+    // Keep tabs, so that the carets line up with the line.
+    let indent: String = line[..span.start].chars()
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    let width = line[span].chars().count().max(1);
+    format!("{}{}", indent, "^".repeat(width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diagnostic_format_test() {
+        let d = Diagnostic::new("calendar", 3, "* Jly 4, Independence Day", 2..5, "Bad month");
+        assert_eq!(d.column, 3);
+        assert_eq!(d.to_string(), "calendar:3:3: warning: Bad month\n    \
+                                   * Jly 4, Independence Day\n      ^^^");
+        assert_eq!(d.format("error"), "calendar:3:3: error: Bad month\n    \
+                                       * Jly 4, Independence Day\n      ^^^");
+
+        let d = Diagnostic::new("calendar", 1, "\tm=jün & x=1, Test", 10..11, "Unknown variable x");
+        assert_eq!(d.column, 10);
+        assert!(d.to_string().ends_with("\n    \t        ^"));
+
+        let d = Diagnostic::new("calendar", 1, "no comma", 8..8, "Expected ','");
+        assert_eq!(d.column, 9);
+        assert!(d.to_string().ends_with("\n            ^"));
+    }
+
+    #[test]
+    fn underline_test() {
+        assert_eq!(underline("m=jün", 2..6), "  ^^^");
+        assert_eq!(underline("m=jün", 4..40), "   ^^");
+        assert_eq!(underline("", 0..0), "^");
+    }
+}
@@ -0,0 +1,96 @@
+use crate::date;
+use chrono::Duration;
+
+// Which Easter the variable e counts from.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Easter {
+    Western,
+    Orthodox,
+}
+
+impl Easter {
+    pub fn from_name(name: &str) -> Option<Easter> {
+        match name.to_lowercase().as_str() {
+            "western" => Some(Easter::Western),
+            "orthodox" => Some(Easter::Orthodox),
+            _ => None,
+        }
+    }
+
+    // date returns Easter Sunday in the given year, as a date in the
+    // Gregorian calendar.
+    pub fn date(&self, year: i32) -> date::Date {
+        match self {
+            Easter::Western => western_easter(year),
+            Easter::Orthodox => orthodox_easter(year),
+        }
+    }
+}
+
+// Anonymous Gregorian algorithm (Meeus/Jones/Butcher).
+fn western_easter(year: i32) -> date::Date {
+    let a = year.rem_euclid(19);
+    let b = year.div_euclid(100);
+    let c = year.rem_euclid(100);
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    date::new_date_opt(year, month as u32, day as u32).unwrap()
+}
+
+// Meeus' Julian algorithm. The resulting Julian calendar date is converted
+// to the Gregorian calendar.
+fn orthodox_easter(year: i32) -> date::Date {
+    let a = year.rem_euclid(4);
+    let b = year.rem_euclid(7);
+    let c = year.rem_euclid(19);
+    let d = (19 * c + 15) % 30;
+    let e = (2 * a + 4 * b - d + 34) % 7;
+    let month = (d + e + 114) / 31;
+    let day = (d + e + 114) % 31 + 1;
+    // Julian Easter is always in March or April, so the difference between
+    // the calendars is the one for March 1 of the same year.
+    let century = year.div_euclid(100);
+    let difference = century - century.div_euclid(4) - 2;
+    let julian = date::new_date_opt(year, month as u32, day as u32).unwrap();
+    julian + Duration::days(difference as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn western_easter_test() {
+        assert_eq!(Easter::Western.date(2019), date::new_date(2019, 4, 21));
+        assert_eq!(Easter::Western.date(2021), date::new_date(2021, 4, 4));
+        assert_eq!(Easter::Western.date(2024), date::new_date(2024, 3, 31));
+        assert_eq!(Easter::Western.date(2025), date::new_date(2025, 4, 20));
+        assert_eq!(Easter::Western.date(2038), date::new_date(2038, 4, 25));
+        assert_eq!(Easter::Western.date(1818), date::new_date(1818, 3, 22));
+    }
+
+    #[test]
+    fn orthodox_easter_test() {
+        assert_eq!(Easter::Orthodox.date(2019), date::new_date(2019, 4, 28));
+        assert_eq!(Easter::Orthodox.date(2021), date::new_date(2021, 5, 2));
+        assert_eq!(Easter::Orthodox.date(2024), date::new_date(2024, 5, 5));
+        assert_eq!(Easter::Orthodox.date(2025), date::new_date(2025, 4, 20));
+        assert_eq!(Easter::Orthodox.date(2100), date::new_date(2100, 5, 2));
+    }
+
+    #[test]
+    fn easter_from_name_test() {
+        assert_eq!(Easter::from_name("Orthodox"), Some(Easter::Orthodox));
+        assert_eq!(Easter::from_name("western"), Some(Easter::Western));
+        assert_eq!(Easter::from_name("eastern"), None);
+    }
+}
@@ -0,0 +1,368 @@
+use crate::calendar::{Calendar, Entry};
+use crate::date;
+use crate::datecalc::{DateChecker, NumberCheck};
+use crate::easter::Easter;
+use crate::error::Error;
+use crate::parser::ParseError;
+use crate::search::Search;
+use crate::utils;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+// new_line returns the calendar line for an item with the date pattern and
+// the description, like "* Dec 25, Christmas". The pattern can be a date,
+// like 2024 Mar 3, or any other date pattern, like m=dec & d>=24 or
+// * jun,aug 15.
+pub fn new_line(pattern: &str, description: &str, easter: Easter) -> Result<String, Error> {
+    let (pattern, description) = (pattern.trim(), description.trim());
+    let checker = DateChecker::with_easter(pattern, easter)
+        .map_err(|error| Error::parse(pattern, error))?;
+    if let Some(newline) = description.find(['\n', '\r']) {
+        let error = ParseError::new("Description can't contain line breaks", newline..newline + 1);
+        return Err(Error::parse(description, error));
+    }
+    if description.is_empty() {
+        return Err(Error::parse(description, ParseError::new("Expected description", 0..0)));
+    }
+    // The line has to be read back as the same item.
+    let line = format!("{}, {}", pattern, description);
+    let parsed = utils::parse_calendar_entry(&line, easter)
+        .map_err(|error| Error::parse(&line, error))?;
+    let split = utils::parse_calendar_line(&line);
+    let same = parsed.is_some_and(|(parsed, _, _)| parsed == checker) &&
+        split == Some((pattern.to_string(), format!(" {}", description)));
+    if !same {
+        let message = "Date pattern would be read differently in the calendar file";
+        return Err(Error::parse(&line, ParseError::new(message, 0..pattern.len())));
+    }
+    Ok(line)
+}
+
+// sort_key returns the key that sorted calendar files are sorted by: the
+// first date that the item matches from 1900 on. Yearly items like
+// * Dec 25 are in the order of their dates in the year, and come before
+// items that happen only once, which are in the order of their dates.
+fn sort_key(checker: &DateChecker) -> Option<date::Date> {
+    checker.occurrences(&date::new_date_opt(1900, 1, 1)?).next()
+}
+
+fn is_comment(line: &str) -> bool {
+    line.trim_start().starts_with('#')
+}
+
+// section returns the range of lines of the section, or None if the text
+// doesn't have it. A section starts with a comment line with its name, like
+// # Birthdays, and ends at the next comment line or at the end of the text.
+fn section(lines: &[&str], name: &str) -> Option<(usize, usize)> {
+    let start = lines.iter().position(|line| {
+        is_comment(line) && line.trim_start()[1..].trim().eq_ignore_ascii_case(name.trim())
+    })? + 1;
+    let end = lines[start..].iter()
+        .position(|line| is_comment(line))
+        .map_or(lines.len(), |k| start + k);
+    Some((start, end))
+}
+
+// insert_line returns the text of the calendar file with the line added,
+// and the line number of the line. The line is added to the end of the
+// section, or to the end of the text if section is None. If the section
+// doesn't exist, it is added to the end of the text. If sorted is true,
+// the line goes before the first item of the section that sorts after it.
+pub fn insert_line(text: &str, line: &str, section_name: Option<&str>, sorted: bool,
+                   easter: Easter) -> (String, usize)
+{
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut lines: Vec<&str> = text.lines().collect();
+    let (start, end) = match section_name {
+        None => (0, lines.len()),
+        Some(name) => match section(&lines, name) {
+            Some(range) => range,
+            None => {
+                if lines.last().is_some_and(|line| !line.trim().is_empty()) {
+                    lines.push("");
+                }
+                let heading = format!("# {}", name.trim());
+                lines.push(&heading);
+                lines.push(line);
+                return (lines.join(newline) + newline, lines.len());
+            },
+        },
+    };
+    // Blank lines at the end of the section separate it from the next one.
+    let mut index = (start..end).rev()
+        .find(|&k| !lines[k].trim().is_empty())
+        .map_or(start, |k| k + 1);
+    if sorted {
+        let key = utils::parse_calendar_entry(line, easter).ok().flatten()
+            .and_then(|(checker, _, _)| sort_key(&checker));
+        let later = (start..end).find(|&k| {
+            match utils::parse_calendar_entry(lines[k], easter) {
+                Ok(Some((checker, _, _))) => sort_key(&checker) > key,
+                _ => false,
+            }
+        });
+        if let Some(k) = later {
+            index = k;
+        }
+    }
+    lines.insert(index, line);
+    (lines.join(newline) + newline, index + 1)
+}
+
+// remove_lines returns text without the lines with line numbers in
+// line_numbers. Other lines, like comments, stay as they are.
+pub fn remove_lines(text: &str, line_numbers: &[usize]) -> String {
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    text.lines()
+        .enumerate()
+        .filter(|(index, _)| !line_numbers.contains(&(index + 1)))
+        .map(|(_, line)| line.to_string() + newline)
+        .collect()
+}
+
+// removed_items returns the items of calendar to remove: the ones on the
+// lines with line_numbers, or all items if there are none, that match
+// search. Only items of the calendar file itself are removed; calendar is
+// read with its included files so that items that match only in those can
+// be reported instead of as no match.
+pub fn removed_items<'a>(calendar: &'a Calendar, line_numbers: &[usize],
+                         search: Option<&Search>) -> Result<Vec<&'a Entry>, Error>
+{
+    let own = |entry: &&Entry| entry.file == calendar.name;
+    for line_number in line_numbers {
+        if !calendar.entries.iter().filter(own).any(|entry| entry.line_number == *line_number) {
+            let message = format!("Line {} of {} isn't a calendar item",
+                                  line_number, calendar.name);
+            return Err(Error::Edit(message));
+        }
+    }
+    let matches = |entry: &&Entry| search.is_none_or(|search| search.matches(&entry.description));
+    let removed: Vec<_> = calendar.entries.iter()
+        .filter(own)
+        .filter(|entry| line_numbers.is_empty() || line_numbers.contains(&entry.line_number))
+        .filter(matches)
+        .collect();
+    if !removed.is_empty() {
+        return Ok(removed);
+    }
+    match calendar.entries.iter().filter(|entry| !own(entry)).find(matches) {
+        Some(entry) if line_numbers.is_empty() => {
+            let message = format!("Items that match are only in included files, like {}:{}; \
+                                   rm changes only {}",
+                                  entry.file, entry.line_number, calendar.name);
+            Err(Error::Edit(message))
+        },
+        _ => Err(Error::Search("No items match".to_string())),
+    }
+}
+
+// append_lines returns text with lines added to the end.
+pub fn append_lines(text: &str, lines: &[&str]) -> String {
+    let newline = if text.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut text = text.to_string();
+    if !text.is_empty() && !text.ends_with('\n') {
+        text.push_str(newline);
+    }
+    for line in lines {
+        text.push_str(line);
+        text.push_str(newline);
+    }
+    text
+}
+
+// is_bounded returns true if check matches only a finite set of numbers.
+fn is_bounded(check: &NumberCheck) -> bool {
+    match check {
+        NumberCheck::Any => false,
+        NumberCheck::Match(_) => true,
+        NumberCheck::Range { last, .. } => *last != u32::MAX,
+        NumberCheck::List(checks) => checks.iter().all(is_bounded),
+    }
+}
+
+// is_one_time returns true for date patterns with fixed years, like
+// 2024 Mar 3 or 2024 Jun 3 +5d, which stop happening after those years.
+fn is_one_time(checker: &DateChecker) -> bool {
+    match checker {
+        DateChecker::Spec { year, .. } => is_bounded(year),
+        DateChecker::Span { start, .. } => is_one_time(start),
+        DateChecker::Expr(_) => false,
+    }
+}
+
+// past_items returns the items of calendar that are done: items with fixed
+// years that don't happen from today on.
+pub fn past_items<'a>(calendar: &'a Calendar, today: &date::Date) -> Vec<&'a Entry> {
+    calendar.entries.iter()
+        .filter(|entry| is_one_time(&entry.checker))
+        .filter(|entry| entry.checker.occurrences(today).next().is_none())
+        .collect()
+}
+
+// write_calendar replaces the calendar file at path with text. It writes
+// a temporary file next to it first and renames it, so that the calendar
+// file isn't left half-written if writing fails. If path is a symbolic
+// link, like a calendar kept in a dotfiles repository, the file it points
+// to is replaced, and the link stays.
+pub fn write_calendar(path: &Path, text: &str) -> Result<(), Error> {
+    let path = &fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let file_name = path.file_name().map_or("calendar".into(), |name| name.to_string_lossy());
+    let temporary = path.with_file_name(format!(".{}.when-rs-new", file_name));
+    let write = || -> std::io::Result<()> {
+        let mut file = fs::File::create(&temporary)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+        if let Ok(metadata) = fs::metadata(path) {
+            fs::set_permissions(&temporary, metadata.permissions())?;
+        }
+        Ok(())
+    };
+    if let Err(error) = write() {
+        let _ = fs::remove_file(&temporary);
+        return Err(Error::io(&temporary, error));
+    }
+    fs::rename(&temporary, path).map_err(|error| Error::io(path, error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CALENDAR: &str = "\
+# Birthdays
+* Mar 9, Alice's birthday
+* Nov 2, Bob's birthday
+
+# Appointments
+2024 Jan 15, Dentist
+2024 May 2, Haircut
+";
+
+    #[test]
+    fn new_line_test() {
+        let easter = Easter::Western;
+        assert_eq!(new_line("2024 Mar 3", " Dentist ", easter).unwrap(), "2024 Mar 3, Dentist");
+        assert_eq!(new_line("m=dec & d>=24", "Holidays", easter).unwrap(), "m=dec & d>=24, Holidays");
+        assert_eq!(new_line("* jun,aug 15", "Review", easter).unwrap(), "* jun,aug 15, Review");
+        assert_eq!(new_line("w=1", "09:30, Standup", easter).unwrap(), "w=1, 09:30, Standup");
+        assert!(new_line("2024 Mar 3, Dentist", "x", easter).is_err());
+        assert_eq!(new_line("2024 Jly 3", "Dentist", easter).err().unwrap().exit_code(), 2);
+        assert!(new_line("2024 Mar 3", "Dentist\nHaircut", easter).is_err());
+        assert!(new_line("2024 Mar 3", " ", easter).is_err());
+    }
+
+    #[test]
+    fn insert_line_test() {
+        let easter = Easter::Western;
+        let insert = |line, section, sorted| insert_line(CALENDAR, line, section, sorted, easter);
+
+        let (text, line_number) = insert("2024 Mar 3, Dentist", None, false);
+        assert_eq!(text, CALENDAR.to_string() + "2024 Mar 3, Dentist\n");
+        assert_eq!(line_number, 8);
+
+        let (text, line_number) = insert("* Jul 1, Carol's birthday", Some("birthdays"), false);
+        assert!(text.starts_with("# Birthdays\n* Mar 9, Alice's birthday\n\
+                                  * Nov 2, Bob's birthday\n* Jul 1, Carol's birthday\n\n"));
+        assert_eq!(line_number, 4);
+
+        let (text, line_number) = insert("* Jul 1, Carol's birthday", Some("Birthdays"), true);
+        assert!(text.starts_with("# Birthdays\n* Mar 9, Alice's birthday\n\
+                                  * Jul 1, Carol's birthday\n* Nov 2, Bob's birthday\n\n"));
+        assert_eq!(line_number, 3);
+
+        let (text, line_number) = insert("2024 Mar 3, Dentist", Some("Appointments"), true);
+        assert!(text.ends_with("2024 Jan 15, Dentist\n2024 Mar 3, Dentist\n2024 May 2, Haircut\n"));
+        assert_eq!(line_number, 7);
+
+        let (text, line_number) = insert("w=1, Standup", Some("Work"), true);
+        assert_eq!(text, CALENDAR.to_string() + "\n# Work\nw=1, Standup\n");
+        assert_eq!(line_number, 10);
+
+        let (text, _) = insert_line("* Mar 9, Alice\r\n", "* Jan 1, New Year", None, true, easter);
+        assert_eq!(text, "* Jan 1, New Year\r\n* Mar 9, Alice\r\n");
+    }
+
+    #[test]
+    fn remove_lines_test() {
+        assert_eq!(remove_lines(CALENDAR, &[2, 6]), "\
+# Birthdays
+* Nov 2, Bob's birthday
+
+# Appointments
+2024 May 2, Haircut
+");
+        assert_eq!(remove_lines("a, b\r\nc, d\r\n", &[1]), "c, d\r\n");
+        assert_eq!(append_lines("a, b", &["# Done", "c, d"]), "a, b\n# Done\nc, d\n");
+        assert_eq!(append_lines("", &["c, d"]), "c, d\n");
+    }
+
+    #[test]
+    fn removed_items_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        fs::write(dir.join("calendar"), format!("#include holidays\n{}", CALENDAR)).unwrap();
+        fs::write(dir.join("holidays"), "* Dec 25, Christmas\n* Jan 1, New Year\n").unwrap();
+        let calendar = Calendar::from_path(&dir.join("calendar"), Easter::Western).unwrap();
+        let lines = |line_numbers: &[usize], search: Option<Search>| {
+            removed_items(&calendar, line_numbers, search.as_ref())
+                .map(|entries| entries.iter().map(|entry| entry.line_number).collect::<Vec<_>>())
+        };
+
+        assert_eq!(lines(&[3], None).unwrap(), vec![3]);
+        assert_eq!(lines(&[], Some(Search::substring("birthday", false, false))).unwrap(),
+                   vec![3, 4]);
+        assert_eq!(lines(&[1], None).err().unwrap().exit_code(), 2);
+        let error = lines(&[], Some(Search::substring("Christmas", false, false))).err().unwrap();
+        assert!(error.to_string().starts_with("Items that match are only in included files, like "));
+        assert!(error.to_string().contains("/holidays:1; rm changes only "));
+        let error = lines(&[3], Some(Search::substring("Christmas", false, false))).err().unwrap();
+        assert_eq!(error.to_string(), "No items match");
+        let error = lines(&[], Some(Search::substring("Easter", false, false))).err().unwrap();
+        assert_eq!(error.to_string(), "No items match");
+    }
+
+    #[test]
+    fn past_items_test() {
+        let calendar = Calendar::from_string("calendar", "\
+2024 Jan 15, Dentist
+2024 Jun 3 +5d, Vacation
+2024 Jun 3 +2d, Conference
+2024 * 1, First of the month
+* Mar 9, Alice's birthday
+y=2024 & m=1 & d=2, Expression
+2023-2025 Jan 1, New Year
+", Easter::Western);
+        let past: Vec<_> = past_items(&calendar, &date::new_date(2024, 6, 5)).iter()
+            .map(|entry| entry.line_number)
+            .collect();
+        assert_eq!(past, vec![1, 3]);
+    }
+
+    #[test]
+    fn write_calendar_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        let path = dir.join("calendar");
+        fs::write(&path, CALENDAR).unwrap();
+        write_calendar(&path, "* Dec 25, Christmas\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "* Dec 25, Christmas\n");
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_calendar_symlink_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        fs::create_dir_all(dir.join("dotfiles")).unwrap();
+        let target = dir.join("dotfiles").join("calendar");
+        let link = dir.join("calendar");
+        fs::write(&target, CALENDAR).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        write_calendar(&link, "* Dec 25, Christmas\n").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "* Dec 25, Christmas\n");
+        assert_eq!(fs::read_dir(dir.join("dotfiles")).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,93 @@
+use std::env;
+use std::ffi::OsString;
+use std::path::Path;
+use when_rs::Error;
+
+// Editor when neither the preferences nor $VISUAL or $EDITOR set one.
+pub const DEFAULT_EDITOR: &str = "vi";
+
+// Editors that open a file at a line given as +LINE before the file name,
+// like vi +12 calendar.
+const LINE_ARGUMENT_EDITORS: &[&str] = &[
+    "vi", "vim", "nvim", "gvim", "view", "emacs", "emacsclient", "nano", "pico",
+    "joe", "jed", "mg", "ne", "kak", "micro",
+];
+
+// choose returns the first editor command that is set and isn't empty: the
+// editor key of the preferences, $VISUAL, $EDITOR, or DEFAULT_EDITOR.
+fn choose(preference: Option<&str>, visual: Option<String>, editor: Option<String>) -> String {
+    preference.map(String::from)
+        .into_iter()
+        .chain(visual)
+        .chain(editor)
+        .find(|command| !command.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string())
+}
+
+// from_environment returns the editor command to use, see choose.
+pub fn from_environment(preference: Option<&str>) -> String {
+    choose(preference, env::var("VISUAL").ok(), env::var("EDITOR").ok())
+}
+
+// supports_line_argument returns true if the editor program opens files at
+// +LINE.
+pub fn supports_line_argument(program: &str) -> bool {
+    Path::new(program).file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| LINE_ARGUMENT_EDITORS.contains(&name))
+}
+
+// command returns the program and the arguments to run editor on the file
+// at path. The editor command is split into words like the shell does, so
+// it can have quoted arguments, like emacsclient -a "" -t; the path is
+// always a single argument. If line is given and the editor supports it,
+// the editor opens the file at that line.
+pub fn command(editor: &str, path: &Path, line: Option<usize>) -> Result<Vec<OsString>, Error> {
+    let words = shell_words::split(editor)
+        .map_err(|error| Error::Editor(format!("Bad editor command {}: {}", editor, error)))?;
+    let program = words.first()
+        .ok_or_else(|| Error::Editor("Editor command is empty".to_string()))?;
+    let mut command: Vec<OsString> = words.iter().map(OsString::from).collect();
+    if let Some(line) = line {
+        if supports_line_argument(program) {
+            command.push(format!("+{}", line).into());
+        }
+    }
+    command.push(path.as_os_str().to_owned());
+    Ok(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choose_test() {
+        let some = |s: &str| Some(s.to_string());
+        assert_eq!(choose(Some("nano"), some("code --wait"), some("vim")), "nano");
+        assert_eq!(choose(None, some("code --wait"), some("vim")), "code --wait");
+        assert_eq!(choose(None, some(""), some("vim")), "vim");
+        assert_eq!(choose(Some(" "), None, None), "vi");
+    }
+
+    #[test]
+    fn supports_line_argument_test() {
+        assert!(supports_line_argument("vi"));
+        assert!(supports_line_argument("/usr/bin/nvim"));
+        assert!(!supports_line_argument("code"));
+        assert!(!supports_line_argument("vim-like"));
+    }
+
+    #[test]
+    fn command_test() {
+        let path = Path::new("/home/alice/My Calendars/calendar");
+        assert_eq!(command("vim", path, None).unwrap(),
+                   vec!["vim", "/home/alice/My Calendars/calendar"]);
+        assert_eq!(command("emacsclient -a '' -t", path, Some(12)).unwrap(),
+                   vec!["emacsclient", "-a", "", "-t", "+12", "/home/alice/My Calendars/calendar"]);
+        assert_eq!(command("\"/opt/My Editor/bin/edit\" --wait", path, Some(12)).unwrap(),
+                   vec!["/opt/My Editor/bin/edit", "--wait", "/home/alice/My Calendars/calendar"]);
+        assert_eq!(command("vim 'unclosed", path, None).err().unwrap().exit_code(), 5);
+        assert!(command("  ", path, None).is_err());
+    }
+}
@@ -0,0 +1,107 @@
+use crate::diagnostic;
+use crate::parser::ParseError;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Errors that make when-rs stop. Each kind of error has its own exit code,
+// see exit_code.
+#[derive(Debug)]
+pub enum Error {
+    // Date or date pattern that can't be parsed, like the --now option.
+    Parse { text: String, error: ParseError },
+    // File that can't be read or written.
+    Io { path: PathBuf, error: io::Error },
+    // Missing or bad preferences.
+    Preferences(String),
+    // Bad range of dates to report, like --future=abc.
+    DateRange(String),
+    // Bad regular expression to search for.
+    Search(String),
+    // Problems in calendar file, already printed as diagnostics.
+    Calendar { path: PathBuf, problems: usize },
+    // Editor that can't be run or that failed.
+    Editor(String),
+    // Calendar label that no calendar has, like --only=wrok.
+    Label(String),
+    // Bad change to the calendar file, like removing a line that isn't an
+    // item.
+    Edit(String),
+}
+
+impl Error {
+    pub fn parse(text: &str, error: ParseError) -> Error {
+        Error::Parse { text: text.to_string(), error }
+    }
+
+    pub fn io(path: &Path, error: io::Error) -> Error {
+        Error::Io { path: path.to_path_buf(), error }
+    }
+
+    // exit_code returns the exit code of when-rs for the error. Exit code 1
+    // is for problems in the calendar, so that check and --strict can be
+    // used in scripts.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Calendar { .. } => 1,
+            Error::Parse { .. } | Error::DateRange(_) | Error::Search(_) | Error::Edit(_) |
+            Error::Label(_) => 2,
+            Error::Preferences(_) => 3,
+            Error::Io { .. } => 4,
+            Error::Editor(_) => 5,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Parse { text, error } => {
+                write!(f, "{}\n    {}\n    {}",
+                       error.message, text, diagnostic::underline(text, error.span.clone()))
+            },
+            Error::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            Error::Preferences(message) => write!(f, "{}", message),
+            Error::DateRange(message) => write!(f, "{}", message),
+            Error::Search(message) => write!(f, "{}", message),
+            Error::Calendar { path, problems } => {
+                write!(f, "{} problem(s) found in {}", problems, path.display())
+            },
+            Error::Editor(message) => write!(f, "{}", message),
+            Error::Edit(message) => write!(f, "{}", message),
+            Error::Label(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datecalc;
+
+    #[test]
+    fn error_display_test() {
+        let text = "2021 Jly 9";
+        let error = Error::parse(text, datecalc::parse_date(text).err().unwrap());
+        assert_eq!(error.to_string(), "Bad month\n    2021 Jly 9\n         ^^^");
+        assert_eq!(error.exit_code(), 2);
+
+        let error = Error::io(Path::new("/nonexistent"),
+                              io::Error::new(io::ErrorKind::NotFound, "not found"));
+        assert_eq!(error.to_string(), "/nonexistent: not found");
+        assert_eq!(error.exit_code(), 4);
+
+        let error = Error::Calendar { path: PathBuf::from("calendar"), problems: 2 };
+        assert_eq!(error.to_string(), "2 problem(s) found in calendar");
+        assert_eq!(error.exit_code(), 1);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::date;
+use crate::datecalc::{BinaryOperator, DateChecker, DateExpression, NumberCheck, Variable};
+use crate::easter::Easter;
+use crate::parser::ParseError;
+use crate::utils;
+use chrono::Duration;
+use std::convert::TryFrom;
+
+// parse_checker parses text to explain, which is either a line of the
+// calendar file, like "* Dec 25, Christmas", or only its date pattern, like
+// * jun,aug 15, which can have commas too.
+pub fn parse_checker(text: &str, easter: Easter) -> Result<DateChecker, ParseError> {
+    let error = match DateChecker::with_easter(text, easter) {
+        Ok(checker) => return Ok(checker),
+        Err(error) => error,
+    };
+    if !text.contains(',') {
+        return Err(error);
+    }
+    match utils::parse_calendar_entry(text, easter)? {
+        Some((checker, _, _)) => Ok(checker),
+        None => Err(ParseError::new("Expected calendar item, not a comment", 0..text.len())),
+    }
+}
+
+// explain returns lines that explain why checker does or doesn't match
+// date: the values of the variables on the date, and then the checker and
+// its parts, each with whether it is true, like this:
+//
+//   Thu 2024 Nov 21: w=4 m=11 d=21 y=2024 a=3 b=2 z=326 e=235 j=60635
+//   false  m=11 & w=4 & a=4
+//   true     m=11    m is 11
+//   true     w=4     w is 4
+//   false    a=4     a is 3
+pub fn explain(checker: &DateChecker, date: &date::Date, easter: Easter) -> Vec<String> {
+    let variables = [
+        Variable::W, Variable::M, Variable::D, Variable::Y, Variable::A,
+        Variable::B, Variable::Z, Variable::E(easter), Variable::J,
+    ];
+    let values: Vec<String> = variables.iter()
+        .map(|v| format!("{}={}", v, v.value(date)))
+        .collect();
+    let mut lines = vec![format!("{}: {}", date.format("%a %Y %b %e"), values.join(" "))];
+    explain_checker(checker, date, 0, &mut lines);
+    lines
+}
+
+// line formats a line of the explanation, with text indented by depth and
+// followed by a note.
+fn line(holds: bool, depth: usize, text: &str, note: &str) -> String {
+    let text = format!("{}{}", "  ".repeat(depth), text);
+    let line = format!("{:5}  {:20}  {}", holds, text, note);
+    line.trim_end().to_string()
+}
+
+fn explain_checker(checker: &DateChecker, date: &date::Date, depth: usize,
+                   lines: &mut Vec<String>)
+{
+    match checker {
+        DateChecker::Spec { year, month, day, weekday } => {
+            lines.push(line(checker.check_date(date), depth, &checker.to_string(), ""));
+            let mut field = |name: &str, check: &NumberCheck, variable: Variable| {
+                let value = variable.value(date);
+                let text = format!("{} {}", name, check);
+                let note = format!("{} is {}", variable, value);
+                let holds = u32::try_from(value).is_ok_and(|value| check.check(value));
+                lines.push(line(holds, depth + 1, &text, &note));
+            };
+            field("year", year, Variable::Y);
+            field("month", month, Variable::M);
+            field("day", day, Variable::D);
+            if *weekday != NumberCheck::Any {
+                field("weekday", weekday, Variable::W);
+            }
+        },
+        DateChecker::Expr(e) => explain_expression(e, date, depth, lines),
+        DateChecker::Span { start, days } => {
+            let text = checker.to_string();
+            match checker.span_day(date) {
+                Some((day, days)) => {
+                    let first = *date - Duration::days((day - 1) as i64);
+                    let note = format!("day {} of {}, starting on {}",
+                                       day, days, first.format("%Y %b %e"));
+                    lines.push(line(true, depth, &text, &note));
+                    explain_checker(start, &first, depth + 1, lines);
+                },
+                None => {
+                    let note = format!("doesn't start on this date or on {} days before",
+                                       days - 1);
+                    lines.push(line(false, depth, &text, &note));
+                },
+            }
+        },
+    }
+}
+
+fn explain_expression(e: &DateExpression, date: &date::Date, depth: usize,
+                      lines: &mut Vec<String>)
+{
+    let holds = e.evaluate(date) != 0;
+    match e {
+        DateExpression::Binary(op @ BinaryOperator::And, _, _) |
+        DateExpression::Binary(op @ BinaryOperator::Or, _, _) => {
+            lines.push(line(holds, depth, &e.to_string(), ""));
+            let mut operands = Vec::new();
+            collect_operands(e, *op, &mut operands);
+            for operand in operands {
+                explain_expression(operand, date, depth + 1, lines);
+            }
+        },
+        DateExpression::Not(inner) => {
+            lines.push(line(holds, depth, &e.to_string(), ""));
+            explain_expression(inner, date, depth + 1, lines);
+        },
+        _ => {
+            let mut variables = Vec::new();
+            e.variables(&mut variables);
+            let values: Vec<String> = variables.iter()
+                .map(|v| format!("{} is {}", v, v.value(date)))
+                .collect();
+            lines.push(line(holds, depth, &e.to_string(), &values.join(", ")));
+        },
+    }
+}
+
+// collect_operands adds to v operands of a chain of operator op, like the
+// three terms of m=11 & w=4 & a=4.
+fn collect_operands<'a>(e: &'a DateExpression, op: BinaryOperator,
+                        v: &mut Vec<&'a DateExpression>)
+{
+    match e {
+        DateExpression::Binary(o, e1, e2) if *o == op => {
+            collect_operands(e1, op, v);
+            collect_operands(e2, op, v);
+        },
+        _ => v.push(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn explain_western(expr: &str, date: date::Date) -> Vec<String> {
+        explain(&DateChecker::new(expr).unwrap(), &date, Easter::Western)
+    }
+
+    #[test]
+    fn parse_checker_test() {
+        let easter = Easter::Western;
+        assert_eq!(parse_checker("* Dec 25, Christmas", easter), parse_checker("* dec 25", easter));
+        assert_eq!(parse_checker("w=1, 09:30, Standup", easter), DateChecker::new("w=1"));
+        assert_eq!(parse_checker("* jun,aug 15", easter), DateChecker::new("* jun,aug 15"));
+        assert_eq!(parse_checker("* jun,aug 15, Review", easter), DateChecker::new("* jun,aug 15"));
+        assert_eq!(parse_checker("* Jly 4, Independence Day", easter).err().unwrap().span, 2..5);
+        assert!(parse_checker("# * Dec 25, Christmas", easter).is_err());
+    }
+
+    #[test]
+    fn explain_expression_test() {
+        let lines = explain_western("m=nov & w=4 & a=4", date::new_date(2024, 11, 21));
+        assert_eq!(lines, vec![
+            "Thu 2024 Nov 21: w=4 m=11 d=21 y=2024 a=3 b=2 z=326 e=235 j=60635",
+            "false  m=11 & w=4 & a=4",
+            "true     m=11                m is 11",
+            "true     w=4                 w is 4",
+            "false    a=4                 a is 3",
+        ]);
+
+        let lines = explain_western("(m=dec & d>=24) | !(w=6 | w=7)", date::new_date(2024, 12, 28));
+        assert_eq!(&lines[1..], &[
+            "true   m=12 & d>=24 | !(w=6 | w=7)",
+            "true     m=12 & d>=24",
+            "true       m=12              m is 12",
+            "true       d>=24             d is 28",
+            "false    !(w=6 | w=7)",
+            "true       w=6 | w=7",
+            "true         w=6             w is 6",
+            "false        w=7             w is 6",
+        ]);
+    }
+
+    #[test]
+    fn explain_pattern_test() {
+        let lines = explain_western("* nov thu", date::new_date(2024, 11, 20));
+        assert_eq!(&lines[1..], &[
+            "false  * nov thu",
+            "true     year *              y is 2024",
+            "true     month 11            m is 11",
+            "true     day *               d is 20",
+            "false    weekday 4           w is 3",
+        ]);
+
+        let lines = explain_western("2020/1 Jan 1", date::new_date(-5, 1, 1));
+        assert_eq!(lines[1], "false  2020/1 jan 1");
+        assert_eq!(lines[2], "false    year 2020/1         y is -5");
+
+        let lines = explain_western("2024 Jun 3 +5d", date::new_date(2024, 6, 5));
+        assert_eq!(lines[1], "true   2024 jun 3 +5d        day 3 of 5, starting on 2024 Jun  3");
+        assert_eq!(lines[2], "true     2024 jun 3");
+        let lines = explain_western("2024 Jun 3 +5d", date::new_date(2024, 6, 8));
+        assert_eq!(lines[1], "false  2024 jun 3 +5d        doesn't start on this date or on 4 days before");
+    }
+}
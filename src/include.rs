@@ -0,0 +1,177 @@
+use crate::diagnostic::Diagnostic;
+use crate::error::Error;
+use std::collections::HashSet;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+// Calendar file read by read_files.
+#[derive(PartialEq, Debug, Clone)]
+pub struct File {
+    pub name: String, // path of the file, used in diagnostics
+    pub text: String,
+}
+
+// parse_include returns the path in an include line, like
+// #include holidays or #include "team calendars/*", and its span in the
+// line. It returns None for other lines.
+fn parse_include(line: &str) -> Option<(&str, Range<usize>)> {
+    let rest = line.trim_start().strip_prefix("#include")?;
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None; // comment like #included
+    }
+    let path = rest.trim();
+    let start = line.len() - rest.trim_start().len();
+    let span = start..start + path.len();
+    match path.strip_prefix('"').and_then(|path| path.strip_suffix('"')) {
+        Some(unquoted) => Some((unquoted, span.start + 1..span.end - 1)),
+        None => Some((path, span)),
+    }
+}
+
+// resolve returns the paths of the files that pattern, from an include line
+// of the file at path, refers to. Relative patterns are relative to the
+// directory of that file. Patterns with *, ? or [ are glob patterns, and
+// can refer to any number of files, in alphabetical order.
+fn resolve(path: &Path, pattern: &str) -> Result<Vec<PathBuf>, String> {
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    if !pattern.contains(['*', '?', '[']) {
+        return Ok(vec![directory.join(pattern)]);
+    }
+    let pattern = if Path::new(pattern).is_absolute() {
+        pattern.to_string()
+    } else {
+        let directory = glob::Pattern::escape(&directory.to_string_lossy());
+        Path::new(&directory).join(pattern).to_string_lossy().into_owned()
+    };
+    let paths = glob::glob(&pattern)
+        .map_err(|error| format!("Bad file name pattern: {}", error.msg))?;
+    Ok(paths.filter_map(Result::ok).filter(|path| path.is_file()).collect())
+}
+
+struct Reader {
+    files: Vec<File>,
+    diagnostics: Vec<Diagnostic>,
+    // Files being read, each included by the one before it.
+    including: Vec<PathBuf>,
+    read: HashSet<PathBuf>,
+}
+
+impl Reader {
+    fn add(&mut self, path: &Path, text: String) {
+        let name = path.display().to_string();
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.including.push(canonical.clone());
+        self.read.insert(canonical);
+        let includes: Vec<_> = text.lines()
+            .enumerate()
+            .filter_map(|(index, line)| {
+                let (pattern, span) = parse_include(line)?;
+                Some((index + 1, line.to_string(), pattern.to_string(), span))
+            })
+            .collect();
+        self.files.push(File { name: name.clone(), text });
+
+        for (line_number, line, pattern, span) in includes {
+            let problem = |message: &str| {
+                Diagnostic::new(&name, line_number, &line, span.clone(), message)
+            };
+            if pattern.is_empty() {
+                self.diagnostics.push(problem("Expected file name after #include"));
+                continue;
+            }
+            let paths = match resolve(path, &pattern) {
+                Ok(paths) => paths,
+                Err(message) => {
+                    self.diagnostics.push(problem(&message));
+                    continue;
+                },
+            };
+            for included in paths {
+                let text = fs::canonicalize(&included)
+                    .and_then(|canonical| Ok((fs::read_to_string(&canonical)?, canonical)));
+                match text {
+                    Err(error) => {
+                        let message = format!("Can't read {}: {}", included.display(), error);
+                        self.diagnostics.push(problem(&message));
+                    },
+                    Ok((_, canonical)) if self.including.contains(&canonical) => {
+                        let message = format!("{} includes itself", included.display());
+                        self.diagnostics.push(problem(&message));
+                    },
+                    // Files included more than once, like holidays included
+                    // by two team calendars, are only read the first time.
+                    Ok((_, canonical)) if self.read.contains(&canonical) => {},
+                    Ok((text, _)) => self.add(&included, text),
+                }
+            }
+        }
+        self.including.pop();
+    }
+}
+
+// read_files reads the calendar file at path, the files that it includes
+// with #include lines, the files they include, and so on. It returns the
+// files in the order they are read, and the problems with include lines,
+// like files that can't be read or that include themselves.
+pub fn read_files(path: &Path) -> Result<(Vec<File>, Vec<Diagnostic>), Error> {
+    let text = fs::read_to_string(path).map_err(|error| Error::io(path, error))?;
+    let mut reader = Reader {
+        files: Vec::new(),
+        diagnostics: Vec::new(),
+        including: Vec::new(),
+        read: HashSet::new(),
+    };
+    reader.add(path, text);
+    Ok((reader.files, reader.diagnostics))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_include_test() {
+        assert_eq!(parse_include("#include holidays"), Some(("holidays", 9..17)));
+        assert_eq!(parse_include("  #include   team/*.cal  "), Some(("team/*.cal", 13..23)));
+        assert_eq!(parse_include("#include \"My Calendar\""), Some(("My Calendar", 10..21)));
+        assert_eq!(parse_include("#include"), Some(("", 8..8)));
+        assert_eq!(parse_include("#included below"), None);
+        assert_eq!(parse_include("# include holidays"), None);
+        assert_eq!(parse_include("* Dec 25, #include"), None);
+    }
+
+    #[test]
+    fn read_files_test() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        fs::create_dir_all(dir.join("team")).unwrap();
+        let write = |name: &str, text: &str| fs::write(dir.join(name), text).unwrap();
+        write("calendar", "#include holidays\n#include team/*.cal\n#include missing\n\
+                           * Mar 9, Alice's birthday\n");
+        write("holidays", "* Dec 25, Christmas\n");
+        write("team/a.cal", "#include ../holidays\nw=1, Standup\n");
+        write("team/b.cal", "#include ../calendar\n");
+
+        let (files, diagnostics) = read_files(&dir.join("calendar")).unwrap();
+        let names: Vec<_> = files.iter()
+            .map(|file| file.name.strip_prefix(&dir.display().to_string()).unwrap())
+            .collect();
+        assert_eq!(names, vec!["/calendar", "/holidays", "/team/a.cal", "/team/b.cal"]);
+        assert_eq!(files[1].text, "* Dec 25, Christmas\n");
+
+        let problems: Vec<_> = diagnostics.iter()
+            .map(|d| (d.file.rsplit('/').next().unwrap(), d.line_number, &d.message[..]))
+            .collect();
+        assert_eq!(problems.len(), 2);
+        assert_eq!(problems[0].0, "b.cal");
+        assert_eq!(problems[0].1, 1);
+        assert!(problems[0].2.ends_with("/team/../calendar includes itself"));
+        assert_eq!(problems[1].0, "calendar");
+        assert_eq!(problems[1].1, 3);
+        assert!(problems[1].2.starts_with("Can't read "));
+        assert_eq!(diagnostics[1].span, 9..16);
+
+        assert!(read_files(&dir.join("nonexistent")).is_err());
+    }
+}
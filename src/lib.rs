@@ -0,0 +1,47 @@
+//! when-rs is a personal calendar: a text file with lines like
+//! `* Dec 25, Christmas` or `w=mon & a=1, Team meeting`. This library
+//! parses calendars and finds their items in a range of dates; the when-rs
+//! binary is its command line client.
+//!
+//! ```
+//! use when_rs::{date, Calendar, Easter};
+//!
+//! let calendar = Calendar::from_string("example", "* Dec 25, Christmas", Easter::Western);
+//! let first = date::new_date_opt(2024, 12, 1).unwrap();
+//! let last = date::new_date_opt(2024, 12, 31).unwrap();
+//! let occurrences = calendar.occurrences(&first, &last);
+//! assert_eq!(occurrences[0].date, date::new_date_opt(2024, 12, 25).unwrap());
+//! assert_eq!(occurrences[0].description, "Christmas");
+//! ```
+
+mod calendar;
+pub mod date;
+mod diagnostic;
+mod easter;
+mod error;
+mod include;
+mod parser;
+mod planner;
+mod utils;
+
+// Modules that the when-rs binary uses, which aren't part of the API of
+// the library.
+#[doc(hidden)]
+pub mod check;
+#[doc(hidden)]
+pub mod datecalc;
+#[doc(hidden)]
+pub mod edit;
+#[doc(hidden)]
+pub mod explain;
+#[doc(hidden)]
+pub mod search;
+
+pub use calendar::{Calendar, Entry, Upcoming};
+pub use datecalc::{DateChecker, Dates, Occurrence};
+pub use diagnostic::Diagnostic;
+pub use easter::Easter;
+pub use error::Error;
+pub use parser::ParseError;
+#[doc(hidden)]
+pub use search::Search;
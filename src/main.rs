@@ -1,18 +1,18 @@
-use clap::{App, Arg, SubCommand};
+use clap::{App, Arg, ArgGroup, SubCommand};
 use std::process;
 use std::env;
+use std::convert::TryFrom;
+use std::ffi::OsString;
 use std::path;
 use std::path::Path;
 use std::fs::{self, File};
-use std::io::{self, BufReader, BufRead, Write};
+use std::io::{self, Write};
 use chrono::{Local, Duration};
-use std::fmt::{Display, Debug};
 use atty::Stream;
+use when_rs::{check, date, datecalc, edit, explain, Calendar, Easter, Error, Search};
 
+mod editor;
 mod preferences;
-mod date;
-mod datecalc;
-mod utils;
 
 fn get_arg() -> clap::ArgMatches<'static> {
     let matches = App::new("when-rs")
@@ -39,9 +39,30 @@ this to be a negative value. Default: -1"#)
             Arg::with_name("calendar")
                 .long("calendar")
                 .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
                 .help(r#"Your calendar file. The default is to use the
-file pointed to by your preferences file, which is
-set up the first time you run when-rs."#)
+files pointed to by the calendars or calendar key
+of your preferences file, which is set up the first
+time you run when-rs. Repeat the option to use more
+calendars, and give them labels, shown before their
+items, like --calendar work=~/work.cal."#)
+        )
+        .arg(
+            Arg::with_name("only")
+                .long("only")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Use only the calendars with these labels, like --only work,home")
+        )
+        .arg(
+            Arg::with_name("exclude")
+                .long("exclude")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Don't use the calendars with these labels")
         )
         .arg(
             Arg::with_name("now")
@@ -50,6 +71,70 @@ set up the first time you run when-rs."#)
                 .help(r#"Pretend today is some other date.
 For example, --now="2022 Jan 1" pretends that today is 2022 January 1."#)
         )
+        .arg(
+            Arg::with_name("easter")
+                .long("easter")
+                .takes_value(true)
+                .possible_values(&["western", "orthodox"])
+                .help(r#"Which Easter variable e in date expressions counts from.
+The default is to use the easter key of your preferences
+file, or western if it isn't set."#)
+        )
+        .arg(
+            Arg::with_name("migration-warnings")
+                .long("migration-warnings")
+                .takes_value(false)
+                .help(r#"Warn about calendar lines that match different dates in the
+report range since a=1 became the first 7 days of the month.
+Earlier versions counted a=1 as days 1-6, a=2 as days 7-13 etc."#)
+        )
+        .arg(
+            Arg::with_name("span-days")
+                .long("span-days")
+                .takes_value(false)
+                .help(r#"Annotate each day of a multi-day event, such as
+2024 Jun 3 .. 2024 Jun 7, with the day of the event, e.g. (day 2 of 5)"#)
+        )
+        .arg(
+            Arg::with_name("strict")
+                .long("strict")
+                .takes_value(false)
+                .help(r#"Fail with exit code 1, without printing the report, if any
+line of the calendar file can't be parsed"#)
+        )
+        .arg(
+            Arg::with_name("search")
+                .long("search")
+                .takes_value(true)
+                .help(r#"Only print items whose descriptions contain this text.
+Unless --future or a subcommand like w sets the range, the
+report then extends one year into the future."#)
+        )
+        .arg(
+            Arg::with_name("grep")
+                .long("grep")
+                .takes_value(true)
+                .help(r#"Like --search, but the text is a regular expression,
+e.g. --grep="^ *(Alice|Bob)'s birthday""#)
+        )
+        .group(
+            ArgGroup::with_name("filter")
+                .args(&["search", "grep"])
+        )
+        .arg(
+            Arg::with_name("ignore-case")
+                .long("ignore-case")
+                .takes_value(false)
+                .requires("filter")
+                .help(r#"Ignore case of letters in --search or --grep"#)
+        )
+        .arg(
+            Arg::with_name("invert-match")
+                .long("invert-match")
+                .takes_value(false)
+                .requires("filter")
+                .help(r#"Only print items that don't match --search or --grep"#)
+        )
         .arg(
             Arg::with_name("header")
                 .long("header")
@@ -66,6 +151,93 @@ For example, --now="2022 Jan 1" pretends that today is 2022 January 1."#)
             SubCommand::with_name("e")
                 .about("runs editor for editing calendar file")
         )
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("adds item to calendar file")
+                .arg(
+                    Arg::with_name("pattern")
+                        .required(true)
+                        .help("Date, like '2024 Mar 3', or date pattern, like '* Dec 25' or 'w=1'")
+                )
+                .arg(
+                    Arg::with_name("description")
+                        .required(true)
+                        .help("Description of the item, like 'Dentist' or '10:00, Dentist'")
+                )
+                .arg(
+                    Arg::with_name("section")
+                        .long("section")
+                        .takes_value(true)
+                        .help("Add the item to the section that starts with comment '# <section>'")
+                )
+                .arg(
+                    Arg::with_name("sorted")
+                        .long("sorted")
+                        .help("Add the item before the first item that sorts after it")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("rm")
+                .about("removes items from calendar file")
+                .arg(
+                    Arg::with_name("line")
+                        .multiple(true)
+                        .help("Line numbers of items to remove; --search or --grep select them by description")
+                )
+                .arg(
+                    Arg::with_name("all")
+                        .long("all")
+                        .help("Remove all items that --search or --grep select, if there are more than one")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("done")
+                .about("moves past one-time items from calendar file to archive file")
+                .arg(
+                    Arg::with_name("archive")
+                        .long("archive")
+                        .takes_value(true)
+                        .help("Archive file; the default is your calendar file with .archive added")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("checks calendar file for errors, impossible dates and duplicates")
+                .arg(
+                    Arg::with_name("file")
+                        .help("Calendar file to check; the default is your calendar")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("explain")
+                .about("explains why a date does or doesn't match a calendar item")
+                .arg(
+                    Arg::with_name("item")
+                        .required(true)
+                        .help("Calendar line, like '* Dec 25, Christmas', or date pattern, like 'm=dec & d>=24'")
+                )
+                .arg(
+                    Arg::with_name("date")
+                        .help("Date to check, like '2024 Dec 25'; the default is today")
+                )
+        )
+        .subcommand(
+            SubCommand::with_name("next")
+                .about("prints the next occurrence of each item, or the next items")
+                .arg(
+                    Arg::with_name("count")
+                        .long("count")
+                        .takes_value(true)
+                        .help("Print the next <count> items, instead of the next occurrence of each")
+                )
+                .arg(
+                    Arg::with_name("horizon")
+                        .long("horizon")
+                        .takes_value(true)
+                        .default_value("3653")
+                        .help("How many days into the future to look for items")
+                )
+        )
         .subcommand(
             SubCommand::with_name("w")
                 .about("print items for the coming week")
@@ -83,25 +255,6 @@ For example, --now="2022 Jan 1" pretends that today is 2022 January 1."#)
     matches
 }
 
-fn expect<Data, Error>(r: Result<Data, Error>, message: &str) -> Data
-    where Error: Debug + Display
-{
-    if let Err(err) = r {
-        eprintln!("{}: {}", message, err);
-        process::exit(-1);
-    }
-    r.unwrap()
-}
-
-//fn expect_option<Data>(r: Option<Data>, message: &str) -> Data
-//{
-//    if r.is_none() {
-//        eprintln!("{}", message);
-//        process::exit(-1);
-//    }
-//    r.unwrap()
-//}
-
 fn prompt(message: &str) -> Option<String> {
     println!("{}", message);
     let mut buffer = String::new();
@@ -112,120 +265,173 @@ fn prompt(message: &str) -> Option<String> {
     }
 }
 
-fn system(v: Vec<&str>) -> bool {
-    let s = v.join(" ");
-    let v2: Vec<&str> = s.split(" ").collect();
-    assert!(v2.len() > 0);
-    let cmd = v2[0];
-    let args: Vec<&str> = v2.iter().skip(1).map(|s| *s).collect();
-    // println!("cmd is {}, args is {:?}", cmd, args);
-    let status = process::Command::new(cmd)
-        .args(args)
+// system runs command, the program and its arguments, and waits for it.
+fn system(command: &[OsString]) -> Result<(), Error> {
+    let program = command[0].to_string_lossy();
+    let status = process::Command::new(&command[0])
+        .args(&command[1..])
         .status()
-        .expect("Failed to execute editor");
-    status.success()
+        .map_err(|error| Error::Editor(format!("Failed to execute editor {}: {}", program, error)))?;
+    if !status.success() {
+        return Err(Error::Editor(format!("Invoking editor failed: {}", status)));
+    }
+    Ok(())
 }
 
 // home_subdir creates path string of a directory that is under current
 // user's home directory, e.g.
 // home_subdir(vec![".when".to_string(), "preferences".to_string()]) returns
 // string "/home/username/.when/preferences".
-fn home_subdir(l: Vec<String>) -> String {
-    let home_dir: String;
-
-    home_dir = expect(env::var("HOME"), "HOME unknown");
+fn home_subdir(l: Vec<String>) -> Result<String, Error> {
+    let home_dir = env::var("HOME")
+        .map_err(|_| Error::Preferences("HOME unknown".to_string()))?;
 
     let path = path::Path::new(&home_dir);
     let mut path_buf = path.to_path_buf();
     for s in l {
         path_buf = path_buf.join(s);
     }
-    let path = path_buf
-                   .to_str()
-                   .unwrap()
-                   .to_string();
-    return path;
+    Ok(path_buf
+        .to_str()
+        .unwrap()
+        .to_string())
 }
 
-fn initialize(_preferences: &str) {
+// initialize sets up preferences and calendar files, if the user wants to.
+// It returns false if the user doesn't.
+fn initialize(preferences: &str) -> Result<bool, Error> {
     if !atty::is(Stream::Stdout) || !atty::is(Stream::Stdin) {
-        eprintln!("Not in interactive mode!");
-        process::exit(-1);
+        let message = format!("{} doesn't exist, and it can only be created in interactive mode",
+                              preferences);
+        return Err(Error::Preferences(message));
     }
 
     // println!("Initializing");
     let a1 = prompt(r#"
 You can now set up your calendar. This involves creating a directory ~/.when, and making
-a couple of files in it. If you want to do this, type y and hit return."#).unwrap();
-    println!("a1 is {}", a1);
+a couple of files in it. If you want to do this, type y and hit return."#).unwrap_or_default();
     if a1 != "y" {
-        process::exit(0);
+        return Ok(false);
     }
     // println!("You said yes");
-    let mut editor = prompt(r#"
+    let default_editor = editor::from_environment(None);
+    let editor = prompt(&format!(r#"
 You can edit your calendar file using your favorite editor. Please enter the command you
-want to use to run your editor, or hit return to accept this default:
-  emacs -nw"#).unwrap();
-     if editor == "" {
-         editor = "emacs -nw".to_string();
-     }
+want to use to run your editor, or hit return to use $VISUAL or $EDITOR, or vi if neither
+is set. Now that is:
+  {}"#, default_editor)).unwrap_or_default();
 
     let when_rs_path = home_subdir(vec![
         ".when-rs".to_string()
-    ]);
+    ])?;
     let preferences_path = home_subdir(vec![
         ".when-rs".to_string(),
         "preferences".to_string()
-    ]);
+    ])?;
     let calendar_path = home_subdir(vec![
         ".when-rs".to_string(),
         "calendar".to_string()
-    ]);
-    println!("preferences_path is {}", preferences_path);
-    println!("editor is {}", editor);
-    if let Err(error) = fs::create_dir(&when_rs_path) {
-        eprintln!("Error creating directory {}: {}", when_rs_path, error);
-        process::exit(-1);
-    }
-    let file = File::create(&preferences_path);
-    if let Err(error) = file {
-        eprintln!("Error creating file {}: {}", preferences_path, error);
-        process::exit(-1);
-    }
-    let mut file = file.unwrap();
-    expect(writeln!(&mut file, "calendar = {}", calendar_path),
-        &format!("Writing to file {}", preferences_path));
-    expect(writeln!(&mut file, "editor = {}", editor),
-        &format!("Writing to file {}", preferences_path));
-
-    let calendar_file = File::create(&calendar_path);
-    if let Err(error) = calendar_file {
-        eprintln!("@#$% Error creating file {}: {}", calendar_path, error);
-        process::exit(-1);
+    ])?;
+    fs::create_dir(&when_rs_path)
+        .map_err(|error| Error::io(Path::new(&when_rs_path), error))?;
+    let preferences_error = |error| Error::io(Path::new(&preferences_path), error);
+    let mut file = File::create(&preferences_path).map_err(preferences_error)?;
+    writeln!(&mut file, "calendar = {}", calendar_path).map_err(preferences_error)?;
+    if !editor.is_empty() {
+        writeln!(&mut file, "editor = {}", editor).map_err(preferences_error)?;
     }
 
-    // process::exit(0);
+    File::create(&calendar_path)
+        .map_err(|error| Error::io(Path::new(&calendar_path), error))?;
+
     println!(r#"
 You can now add items to your calendar file. Do ``when-rs --help'' for more information.
     "#);
+    Ok(true)
+}
+
+// next_occurrences returns the items that the next subcommand prints: the
+// next occurrence of each calendar entry, or with --count, the next count
+// occurrences of all entries, from today up to the horizon.
+fn next_occurrences(calendar: &Calendar, matches: &clap::ArgMatches, today: &date::Date) ->
+    Result<Vec<datecalc::Occurrence>, Error>
+{
+    let horizon = matches.value_of("horizon").unwrap();
+    let days = match horizon.parse::<u64>() {
+        Ok(days) => days,
+        Err(_) if !horizon.is_empty() && horizon.bytes().all(|b| b.is_ascii_digit()) => u64::MAX,
+        Err(_) => {
+            return Err(Error::DateRange(format!("--horizon must be a number, not {}", horizon)));
+        },
+    };
+    // Horizons past the last date that there is end on that date.
+    let until = i64::try_from(days).ok()
+        .and_then(Duration::try_days)
+        .and_then(|days| today.checked_add_signed(days))
+        .unwrap_or(date::Date::MAX);
+
+    if let Some(count) = matches.value_of("count") {
+        let count = count.parse::<usize>()
+            .map_err(|_| Error::DateRange(format!("--count must be a number, not {}", count)))?;
+        return Ok(calendar.upcoming(today, &until).take(count).collect());
+    }
+    let occurrences = calendar.entries.iter()
+        .filter_map(|entry| entry.next_occurrence(today, &until))
+        .collect();
+    Ok(datecalc::sort_dates(occurrences))
+}
+
+// warn_week_of_month_change prints a warning if the calendar line matched
+// different dates in the range between date1 and date2 before the meaning
+// of a= changed.
+fn warn_week_of_month_change(file: &str, line_number: usize, line: &str,
+                             checker: &datecalc::DateChecker, dates: &[date::Date],
+                             date1: &date::Date, date2: &date::Date)
+{
+    let legacy = match checker.legacy_week_of_month() {
+        Some(legacy) => legacy,
+        None => return,
+    };
+    let legacy_dates = legacy.check_date_range(date1, date2);
+    if legacy_dates == dates {
+        return;
+    }
+    let format_dates = |dates: &[date::Date]| {
+        let v: Vec<String> = dates.iter()
+            .map(|d| d.format("%Y %b %e").to_string())
+            .collect();
+        if v.is_empty() { "nothing".to_string() } else { v.join(", ") }
+    };
+    eprintln!("{}:{}: warning: meaning of a= changed for line: {}",
+              file, line_number, line);
+    eprintln!("    before it matched: {}", format_dates(&legacy_dates));
+    eprintln!("    now it matches:    {}", format_dates(dates));
 }
 
 fn main() {
+    if let Err(error) = run() {
+        eprintln!("when-rs: {}", error);
+        process::exit(error.exit_code());
+    }
+}
+
+fn run() -> Result<(), Error> {
     let preferences_path = home_subdir(vec![
         ".when-rs".to_string(),
         "preferences".to_string()
-    ]);
-    println!("preferences_path is {}", preferences_path);
-
-    let it = std::fs::read_to_string(&preferences_path);
-    let preferences: String;
-    if it.is_err() {
-        initialize(&preferences_path);
-        preferences = std::fs::read_to_string(&preferences_path).unwrap();
-        
-    } else {
-        preferences = it.unwrap();
-    }
+    ])?;
+
+    let preferences_error = |error| Error::io(Path::new(&preferences_path), error);
+    let preferences = match fs::read_to_string(&preferences_path) {
+        Ok(preferences) => preferences,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            if !initialize(&preferences_path)? {
+                return Ok(());
+            }
+            fs::read_to_string(&preferences_path).map_err(preferences_error)?
+        },
+        Err(error) => return Err(preferences_error(error)),
+    };
 
     // Read preferences from preferences file.
     let hashmap_preferences = preferences::parse_lines(preferences.lines());
@@ -239,64 +445,237 @@ fn main() {
     let mut arg_future: i32 = 14;
     let mut arg_past: i32 = 1;
 
+    let days_error = |option: &str, n: &str| {
+        Error::DateRange(format!("--{} must be a number of days, not {}", option, n))
+    };
+
     if let Some(n) = matches.value_of("future") {
-        arg_future = expect(n.parse::<i32>(), matches.usage());
+        arg_future = n.parse::<i32>().map_err(|_| days_error("future", n))?;
     }
 
     if let Some(n) = matches.value_of("past") {
-        arg_past = expect(n.parse::<i32>(), matches.usage());
+        arg_past = n.parse::<i32>().map_err(|_| days_error("past", n))?;
     }
 
-    // Get calendar from calendar file, specified in command line or
-    // preferences.
-    let calendar;
-    if let Some(path) = matches.value_of("calendar") {
-        calendar = Path::new(path);
+    // Get calendars, each with an optional label, from calendar files
+    // specified in command line or preferences.
+    let mut calendars = if let Some(values) = matches.values_of("calendar") {
+        values.map(preferences::parse_calendar).collect()
+    } else if let Some(list) = hashmap_preferences.get("calendars") {
+        preferences::parse_calendars(list)
     } else if let Some(path) = hashmap_preferences.get("calendar") {
-        calendar = Path::new(path);
+        vec![(None, path.clone())]
     } else {
-        eprintln!("Configuration doesn't have calendar key");
-        process::exit(-1);
+        let message = "Configuration doesn't have calendar key".to_string();
+        return Err(Error::Preferences(message));
+    };
+    let labels = |option| preferences::parse_labels(matches.values_of(option).into_iter().flatten());
+    let (only, exclude) = (labels("only"), labels("exclude"));
+    for label in only.iter().chain(&exclude) {
+        if !calendars.iter().any(|(l, _)| l.as_deref() == Some(*label)) {
+            return Err(Error::Label(format!("No calendar has label {}", label)));
+        }
+    }
+    calendars.retain(|(label, _)| {
+        let label = label.as_deref().unwrap_or_default();
+        (only.is_empty() || only.contains(&label)) && !exclude.contains(&label)
+    });
+    if calendars.is_empty() {
+        return Err(Error::Label("No calendars are selected".to_string()));
     }
+    // Subcommands that change a calendar file, like e and add, change the
+    // first one.
+    let calendar = Path::new(&calendars[0].1);
+
+    let easter_name = matches.value_of("easter")
+        .or_else(|| hashmap_preferences.get("easter").map(|s| &s[..]))
+        .unwrap_or("western");
+    let easter = match Easter::from_name(easter_name) {
+        Some(easter) => easter,
+        None => {
+            let message = format!("Bad easter preference: {}", easter_name);
+            return Err(Error::Preferences(message));
+        }
+    };
 
     let today;
     if let Some(now) = matches.value_of("now") {
-        let today_opt = datecalc::parse_date(now);
-        if today_opt.is_none() {
-            eprintln!("Bad date string: {}", now);
-            process::exit(-1);
-        }
-        today = today_opt.unwrap();
+        today = datecalc::parse_date(now).map_err(|error| Error::parse(now, error))?;
     } else {
-        today = Local::today().naive_local();
+        today = Local::now().date_naive();
     }
 
     if matches.is_present("e") {
-        if let Some(editor) = hashmap_preferences.get("editor") {
-            // println!("Invoking editor {}", editor);
-            let command_arg = format!("{}", calendar.to_str().unwrap());
-            let cmd_str = [&editor[..], &command_arg[..]].join(" ");
-            let v = cmd_str.split(" ");
-            if system(v.collect()) {
-                process::exit(0);
-            } else {
-                eprintln!("Invoking editor failed");
-                process::exit(-1);
+        let editor = editor::from_environment(hashmap_preferences.get("editor").map(|s| &s[..]));
+        // Like crontab -e, check the calendar after editing, and open it
+        // again at the first bad line until it has no errors or the user
+        // gives up.
+        let mut line_number = None;
+        loop {
+            system(&editor::command(&editor, calendar, line_number)?)?;
+            let calendar_items = Calendar::from_path(calendar, easter)?;
+            let diagnostics = &calendar_items.diagnostics;
+            if diagnostics.is_empty() {
+                return Ok(());
+            }
+            for diagnostic in diagnostics {
+                eprintln!("{}", diagnostic.format("error"));
+            }
+            let problems = Error::Calendar { path: calendar.to_path_buf(), problems: diagnostics.len() };
+            if !atty::is(Stream::Stdout) || !atty::is(Stream::Stdin) {
+                return Err(problems);
+            }
+            let answer = prompt("Edit calendar file again? [Y/n]");
+            match answer.as_deref().map(str::trim) {
+                Some("") | Some("y") | Some("Y") => {},
+                _ => return Err(problems),
             }
+            // Problems in included files are shown, but the editor opens
+            // the calendar file itself.
+            line_number = diagnostics.iter()
+                .filter(|diagnostic| diagnostic.file == calendar_items.name)
+                .map(|diagnostic| diagnostic.line_number)
+                .min();
         }
     }
 
+    if let Some(check_matches) = matches.subcommand_matches("check") {
+        let files: Vec<&Path> = match check_matches.value_of("file") {
+            Some(file) => vec![Path::new(file)],
+            None => calendars.iter().map(|(_, path)| Path::new(path)).collect(),
+        };
+        let mut first_problems = None;
+        for file in files {
+            let diagnostics = check::check_path(file, easter, &today)?;
+            for diagnostic in &diagnostics {
+                eprintln!("{}", diagnostic.format("error"));
+            }
+            if !diagnostics.is_empty() && first_problems.is_none() {
+                first_problems = Some(Error::Calendar {
+                    path: file.to_path_buf(),
+                    problems: diagnostics.len(),
+                });
+            }
+        }
+        return first_problems.map_or(Ok(()), Err);
+    }
+
+    if let Some(add_matches) = matches.subcommand_matches("add") {
+        let line = edit::new_line(add_matches.value_of("pattern").unwrap(),
+                                  add_matches.value_of("description").unwrap(), easter)?;
+        let text = fs::read_to_string(calendar).map_err(|error| Error::io(calendar, error))?;
+        let (text, line_number) = edit::insert_line(
+            &text, &line, add_matches.value_of("section"), add_matches.is_present("sorted"),
+            easter);
+        edit::write_calendar(calendar, &text)?;
+        println!("{}:{}: {}", calendar.display(), line_number, line);
+        return Ok(());
+    }
+
+    if let Some(explain_matches) = matches.subcommand_matches("explain") {
+        let item = explain_matches.value_of("item").unwrap();
+        let checker = explain::parse_checker(item, easter)
+            .map_err(|error| Error::parse(item, error))?;
+        let date = match explain_matches.value_of("date") {
+            Some(text) => datecalc::parse_date(text).map_err(|error| Error::parse(text, error))?,
+            None => today,
+        };
+        for line in explain::explain(&checker, &date, easter) {
+            println!("{}", line);
+        }
+        return Ok(());
+    }
+
     let arg_past: i64 = arg_past.into();
     let mut arg_future: i64 = arg_future.into();
 
+    // Filter of items by their descriptions.
+    let ignore_case = matches.is_present("ignore-case");
+    let invert = matches.is_present("invert-match");
+    let search = if let Some(text) = matches.value_of("search") {
+        Some(Search::substring(text, ignore_case, invert))
+    } else if let Some(pattern) = matches.value_of("grep") {
+        Some(Search::regex(pattern, ignore_case, invert)?)
+    } else {
+        None
+    };
+
+    if let Some(rm_matches) = matches.subcommand_matches("rm") {
+        let mut line_numbers = Vec::new();
+        for value in rm_matches.values_of("line").into_iter().flatten() {
+            let line_number = value.parse::<usize>()
+                .map_err(|_| Error::Edit(format!("Bad line number: {}", value)))?;
+            line_numbers.push(line_number);
+        }
+        if line_numbers.is_empty() && search.is_none() {
+            return Err(Error::Edit("Give line numbers or --search or --grep".to_string()));
+        }
+        let text = fs::read_to_string(calendar).map_err(|error| Error::io(calendar, error))?;
+        let calendar_items = Calendar::from_path(calendar, easter)?;
+        let removed = edit::removed_items(&calendar_items, &line_numbers, search.as_ref())?;
+        if line_numbers.is_empty() && removed.len() > 1 && !rm_matches.is_present("all") {
+            for entry in &removed {
+                eprintln!("{}:{}: {}", calendar.display(), entry.line_number, entry.line);
+            }
+            let message = format!("{} items match; use --all to remove all of them", removed.len());
+            return Err(Error::Edit(message));
+        }
+        let removed_lines: Vec<_> = removed.iter().map(|entry| entry.line_number).collect();
+        edit::write_calendar(calendar, &edit::remove_lines(&text, &removed_lines))?;
+        for entry in &removed {
+            println!("{}:{}: {}", calendar.display(), entry.line_number, entry.line);
+        }
+        return Ok(());
+    }
+
+    if let Some(done_matches) = matches.subcommand_matches("done") {
+        let archive = match done_matches.value_of("archive") {
+            Some(path) => path::PathBuf::from(path),
+            None => {
+                let mut path = calendar.as_os_str().to_owned();
+                path.push(".archive");
+                path::PathBuf::from(path)
+            },
+        };
+        let text = fs::read_to_string(calendar).map_err(|error| Error::io(calendar, error))?;
+        let calendar_items = Calendar::from_string(&calendar.display().to_string(), &text, easter);
+        let past = edit::past_items(&calendar_items, &today);
+        if past.is_empty() {
+            return Ok(());
+        }
+        let archive_text = match fs::read_to_string(&archive) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(Error::io(&archive, error)),
+        };
+        let heading = format!("# Done on {}", today.format("%Y %b %-d"));
+        let mut lines = vec![&heading[..]];
+        lines.extend(past.iter().map(|entry| &entry.line[..]));
+        // The archive is written first, so that if writing the calendar
+        // fails, the items are in both files instead of in neither.
+        edit::write_calendar(&archive, &edit::append_lines(&archive_text, &lines))?;
+        let past_lines: Vec<_> = past.iter().map(|entry| entry.line_number).collect();
+        edit::write_calendar(calendar, &edit::remove_lines(&text, &past_lines))?;
+        for entry in &past {
+            println!("{}:{}: {}", calendar.display(), entry.line_number, entry.line);
+        }
+        return Ok(());
+    }
+
     if matches.is_present("y") {
         arg_future = 366;
     } else if matches.is_present("m") {
         arg_future = 31;
     } else if matches.is_present("w") {
         arg_future = 7;
+    } else if search.is_some() && matches.occurrences_of("future") == 0 {
+        arg_future = 366;
     }
 
+    let migration_warnings = matches.is_present("migration-warnings");
+    let span_days = matches.is_present("span-days");
+    let strict = matches.is_present("strict");
+
     let mut header: bool = true;
 
     if matches.is_present("noheader") {
@@ -304,57 +683,100 @@ fn main() {
     }
 
     // let today = Local::today().naive_local();
-    let yesterday = today.pred();
-    let tomorrow = today.succ();
-    let date1 = today - Duration::days(arg_past);
-    let date2 = today + Duration::days(arg_future);
+    let out_of_range = || {
+        Error::DateRange("Report range is outside of supported dates".to_string())
+    };
+    let yesterday = today.pred_opt().ok_or_else(out_of_range)?;
+    let tomorrow = today.succ_opt().ok_or_else(out_of_range)?;
+    let date1 = today.checked_sub_signed(Duration::days(arg_past)).ok_or_else(out_of_range)?;
+    let date2 = today.checked_add_signed(Duration::days(arg_future)).ok_or_else(out_of_range)?;
+    if date2 < date1 {
+        let message = format!("Report range ends before it starts: {} .. {}", date1, date2);
+        return Err(Error::DateRange(message));
+    }
 
     // eprintln!("calendar file is {:?}", calendar);
-    let file = File::open(calendar);
-    if let Err(err) = file {
-        eprintln!("Failure opening {}: {}", calendar.to_str().unwrap(), err);
-        process::exit(-1);
+    // Items of all calendars, with their labels before their descriptions,
+    // and the number of problems found in each calendar.
+    let mut calendar_items = Calendar {
+        name: calendar.display().to_string(),
+        entries: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    let mut problems = Vec::new();
+    for (label, path) in &calendars {
+        let mut items = Calendar::from_path(Path::new(path), easter)?;
+        if let Some(search) = &search {
+            items.entries.retain(|entry| search.matches(&entry.description));
+        }
+        if let Some(label) = label {
+            for entry in &mut items.entries {
+                entry.description = format!("[{}] {}", label, entry.description);
+            }
+        }
+        problems.push((path, items.diagnostics.len()));
+        calendar_items.entries.append(&mut items.entries);
+        calendar_items.diagnostics.append(&mut items.diagnostics);
     }
-    let file = file.unwrap();
-    let reader = BufReader::new(file);
+
+    // println!("date1 is {:?}", date1);
+    // println!("date2 is {:?}", date2);
+
+    if migration_warnings {
+        for entry in &calendar_items.entries {
+            let dates = entry.checker.check_date_range(&date1, &date2);
+            warn_week_of_month_change(&entry.file, entry.line_number, &entry.line,
+                                      &entry.checker, &dates, &date1, &date2);
+        }
+    }
+
+    let diagnostics = &calendar_items.diagnostics;
+    let severity = if strict { "error" } else { "warning" };
+    for diagnostic in diagnostics {
+        eprintln!("{}", diagnostic.format(severity));
+    }
+    if strict {
+        if let Some((path, count)) = problems.into_iter().find(|(_, count)| *count > 0) {
+            return Err(Error::Calendar { path: path.into(), problems: count });
+        }
+    }
+
+    // Bad options of next are reported before anything is printed.
+    let mut sorted_dates = match matches.subcommand_matches("next") {
+        Some(next_matches) => next_occurrences(&calendar_items, next_matches, &today)?,
+        None => calendar_items.occurrences(&date1, &date2),
+    };
 
     if header {
         let now = Local::now();
         println!("{} {}\n", today.format("%a %Y %b %e"), now.format("%R"));
     }
 
-    // println!("date1 is {:?}", date1);
-    // println!("date2 is {:?}", date2);
-
-    let mut all_dates: Vec<(date::Date, String)> = Vec::new();
-    for line in reader.lines() {
-        if let Ok(line_str) = line {
-            // eprintln!("Line: {}", line_str);
-            if let Some((expr, descr)) = utils::parse_calendar_line(&line_str) {
-                // eprintln!(" -- expression: {}", expr);
-                // eprintln!(" -- description: {}", descr);
-                if let Ok(checker) = datecalc::DateChecker::new(&expr) {
-                    let dates = checker.check_date_range(&date1, &date2);
-                    for date in &dates {
-                        all_dates.push((*date, descr.clone()));
-                    }
-                }
+    // Print the result. If any item has time, times are printed in a
+    // column between the date and the description.
+    if span_days {
+        for date in &mut sorted_dates {
+            if let Some((day, days)) = date.span_day {
+                date.description = format!("{} (day {} of {})", date.description, day, days);
             }
         }
     }
-
-    // Sort and print the result.
-    let sorted_dates = datecalc::sort_dates(all_dates);
+    let time_column = sorted_dates.iter().any(|o| o.time.is_some());
     for date in sorted_dates {
-        let date_str = date.0.format("%Y %b %e");
-        if date.0 == today {
-            println!("today      {} {}", date_str, date.1);
-        } else if date.0 == yesterday {
-            println!("yesterday  {} {}", date_str, date.1);
-        } else if date.0 == tomorrow {
-            println!("tomorrow   {} {}", date_str, date.1);
+        let mut date_str = date.date.format("%Y %b %e").to_string();
+        if time_column {
+            let time_str = date.time.map(|t| t.to_string()).unwrap_or_default();
+            date_str = format!("{} {:11}", date_str, time_str);
+        }
+        if date.date == today {
+            println!("today      {}  {}", date_str, date.description);
+        } else if date.date == yesterday {
+            println!("yesterday  {}  {}", date_str, date.description);
+        } else if date.date == tomorrow {
+            println!("tomorrow   {}  {}", date_str, date.description);
         } else {
-            println!("           {} {}", date_str, date.1);
+            println!("           {}  {}", date_str, date.description);
         }
     }
+    Ok(())
 }
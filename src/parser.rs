@@ -0,0 +1,443 @@
+use crate::datecalc::{self, BinaryOperator, DateExpression, Variable};
+use crate::easter::Easter;
+use std::fmt;
+use std::ops::Range;
+
+// Tokens of a date expression such as (m=dec & d>=24) | !(w=6 | w=7).
+#[derive(PartialEq, Debug, Clone)]
+pub enum Token {
+    Number(i64),
+    Name(String), // variable, month or weekday name
+    Operator(BinaryOperator),
+    Not,
+    LeftParen,
+    RightParen,
+}
+
+// Error in a date expression. The span is the range of bytes of the
+// expression where the problem is; it is empty if something is missing,
+// e.g. at the end of the expression.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub span: Range<usize>,
+}
+
+impl ParseError {
+    pub fn new(message: &str, span: Range<usize>) -> ParseError {
+        ParseError { message: message.to_string(), span }
+    }
+
+    // shift moves the span by offset, for errors in a part of a longer
+    // string.
+    pub fn shift(self, offset: usize) -> ParseError {
+        let span = self.span.start + offset..self.span.end + offset;
+        ParseError { span, ..self }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Operator(op) => write!(f, "{}", op),
+            Token::Not => write!(f, "!"),
+            Token::LeftParen => write!(f, "("),
+            Token::RightParen => write!(f, ")"),
+        }
+    }
+}
+
+// tokenize returns tokens of s, each with its span in s.
+pub fn tokenize(s: &str) -> Result<Vec<(Token, Range<usize>)>, ParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        if c.is_alphanumeric() {
+            let mut word = c.to_string();
+            while let Some(&(_, c)) = chars.peek() {
+                if !c.is_alphanumeric() {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            let span = position..position + word.len();
+            if c.is_ascii_digit() {
+                match word.parse::<i64>() {
+                    Ok(n) => tokens.push((Token::Number(n), span)),
+                    Err(_) => {
+                        let message = format!("Bad number {}", word);
+                        return Err(ParseError::new(&message, span));
+                    },
+                }
+            } else {
+                tokens.push((Token::Name(word), span));
+            }
+            continue;
+        }
+        // Two-character operators: ==, !=, <=, >=.
+        let followed_by_equal = chars.peek().map(|&(_, c)| c) == Some('=');
+        let token = match c {
+            '=' => Token::Operator(BinaryOperator::Equal),
+            '!' if followed_by_equal => Token::Operator(BinaryOperator::NotEqual),
+            '<' if followed_by_equal => Token::Operator(BinaryOperator::LessEqual),
+            '>' if followed_by_equal => Token::Operator(BinaryOperator::GreaterEqual),
+            '!' => Token::Not,
+            '<' => Token::Operator(BinaryOperator::Less),
+            '>' => Token::Operator(BinaryOperator::Greater),
+            '&' => Token::Operator(BinaryOperator::And),
+            '|' => Token::Operator(BinaryOperator::Or),
+            '+' => Token::Operator(BinaryOperator::Add),
+            '-' => Token::Operator(BinaryOperator::Subtract),
+            '%' => Token::Operator(BinaryOperator::Modulo),
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            _ => {
+                let message = format!("Unexpected character '{}'", c);
+                return Err(ParseError::new(&message, position..position + c.len_utf8()));
+            },
+        };
+        let mut end = position + 1;
+        if followed_by_equal && "=!<>".contains(c) {
+            chars.next();
+            end += 1;
+        }
+        tokens.push((token, position..end));
+    }
+    Ok(tokens)
+}
+
+// Recursive descent parser for the grammar below, from the lowest to the
+// highest precedence:
+//
+//   expression := conjunction ('|' conjunction)*
+//   conjunction := negation ('&' negation)*
+//   negation := '!' negation | comparison
+//   comparison := sum (('=' | '!=' | '<' | '<=' | '>' | '>=') sum)?
+//   sum := product (('+' | '-') product)*
+//   product := unary ('%' unary)*
+//   unary := '-' unary | primary
+//   primary := number | variable | month | weekday | '(' expression ')'
+//
+// Note that ! has lower precedence than comparisons, so !w=6 means !(w=6).
+struct Parser {
+    tokens: Vec<(Token, Range<usize>)>,
+    pos: usize,
+    end: usize, // length of the expression
+    easter: Easter,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    // span returns the span of the next token in the expression, or the
+    // empty span at its end.
+    fn span(&self) -> Range<usize> {
+        self.tokens.get(self.pos).map(|(_, span)| span.clone()).unwrap_or(self.end..self.end)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        if token.is_some() {
+            self.pos += 1;
+        }
+        token
+    }
+
+    // error returns error at the next token; the message mentions the token.
+    fn error(&self, expected: &str) -> ParseError {
+        let message = match self.peek() {
+            Some(token) => format!("{}, found {}", expected, token),
+            None => format!("{} at end of expression", expected),
+        };
+        ParseError::new(&message, self.span())
+    }
+
+    // next_operator consumes the next token if it is one of operators.
+    fn next_operator(&mut self, operators: &[BinaryOperator]) -> Option<BinaryOperator> {
+        match self.peek() {
+            Some(Token::Operator(op)) if operators.contains(op) => {
+                let op = *op;
+                self.next();
+                Some(op)
+            },
+            _ => None,
+        }
+    }
+
+    // binary parses a left-associative chain of operands separated by
+    // operators.
+    fn binary(&mut self, operators: &[BinaryOperator],
+              operand: fn(&mut Parser) -> Result<DateExpression, ParseError>) ->
+        Result<DateExpression, ParseError>
+    {
+        let mut left = operand(self)?;
+        while let Some(op) = self.next_operator(operators) {
+            let right = operand(self)?;
+            left = DateExpression::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn expression(&mut self) -> Result<DateExpression, ParseError> {
+        self.binary(&[BinaryOperator::Or], Parser::conjunction)
+    }
+
+    fn conjunction(&mut self) -> Result<DateExpression, ParseError> {
+        self.binary(&[BinaryOperator::And], Parser::negation)
+    }
+
+    fn negation(&mut self) -> Result<DateExpression, ParseError> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            let inner = self.negation()?;
+            return Ok(DateExpression::Not(Box::new(inner)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<DateExpression, ParseError> {
+        let left = self.sum()?;
+        let op = self.next_operator(&[
+            BinaryOperator::Equal,
+            BinaryOperator::NotEqual,
+            BinaryOperator::Less,
+            BinaryOperator::LessEqual,
+            BinaryOperator::Greater,
+            BinaryOperator::GreaterEqual,
+        ]);
+        match op {
+            Some(op) => {
+                let right = self.sum()?;
+                Ok(DateExpression::Binary(op, Box::new(left), Box::new(right)))
+            },
+            None => Ok(left),
+        }
+    }
+
+    fn sum(&mut self) -> Result<DateExpression, ParseError> {
+        self.binary(&[BinaryOperator::Add, BinaryOperator::Subtract], Parser::product)
+    }
+
+    fn product(&mut self) -> Result<DateExpression, ParseError> {
+        self.binary(&[BinaryOperator::Modulo], Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<DateExpression, ParseError> {
+        if self.peek() == Some(&Token::Operator(BinaryOperator::Subtract)) {
+            self.next();
+            let inner = self.unary()?;
+            return Ok(DateExpression::Negate(Box::new(inner)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<DateExpression, ParseError> {
+        let span = self.span();
+        match self.peek().cloned() {
+            Some(Token::LeftParen) => {
+                self.next();
+                let inner = self.expression()?;
+                if self.peek() != Some(&Token::RightParen) {
+                    return Err(self.error("Expected ')'"));
+                }
+                self.next();
+                Ok(inner)
+            },
+            Some(Token::Number(n)) => {
+                self.next();
+                Ok(DateExpression::Number(n))
+            },
+            Some(Token::Name(name)) => {
+                self.next();
+                new_name(&name, self.easter)
+                    .map_err(|message| ParseError::new(&message, span))
+            },
+            _ => Err(self.error("Expected number, variable or '('")),
+        }
+    }
+}
+
+// new_name resolves a name used in expression: a single letter is a
+// variable, a longer name is a month, e.g. m=jan, or a weekday, e.g. w=fri.
+// No prefix of two or more letters is shared by a month and a weekday.
+fn new_name(name: &str, easter: Easter) -> Result<DateExpression, String> {
+    let lname = name.to_lowercase();
+    if lname.len() == 1 {
+        return match Variable::from_name(&lname, easter) {
+            Some(v) => Ok(DateExpression::Variable(v)),
+            None => Err(format!("Unknown variable {}", name)),
+        };
+    }
+    match datecalc::parse_month(&lname).or_else(|| datecalc::parse_weekday(&lname)) {
+        Some(n) => Ok(DateExpression::Number(n as i64)),
+        None => Err(datecalc::ambiguous_name(&lname)
+                    .unwrap_or_else(|| format!("Unknown name {}", name))),
+    }
+}
+
+pub fn parse_expression(s: &str, easter: Easter) -> Result<DateExpression, ParseError> {
+    let tokens = tokenize(s)?;
+    let mut parser = Parser { tokens, pos: 0, end: s.len(), easter };
+    let expression = parser.expression()?;
+    if parser.peek().is_some() {
+        return Err(parser.error("Expected operator"));
+    }
+    Ok(expression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary(op: BinaryOperator, e1: DateExpression, e2: DateExpression) -> DateExpression {
+        DateExpression::Binary(op, Box::new(e1), Box::new(e2))
+    }
+
+    fn var(v: Variable) -> DateExpression {
+        DateExpression::Variable(v)
+    }
+
+    fn num(n: i64) -> DateExpression {
+        DateExpression::Number(n)
+    }
+
+    fn parse_expression_western(s: &str) -> Result<DateExpression, ParseError> {
+        parse_expression(s, Easter::Western)
+    }
+
+    fn token_list(s: &str) -> Result<Vec<Token>, ParseError> {
+        tokenize(s).map(|v| v.into_iter().map(|(token, _)| token).collect())
+    }
+
+    #[test]
+    fn tokenize_test() {
+        let tokens = token_list("!(m=dec&d>=24)").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Not,
+            Token::LeftParen,
+            Token::Name("m".to_string()),
+            Token::Operator(BinaryOperator::Equal),
+            Token::Name("dec".to_string()),
+            Token::Operator(BinaryOperator::And),
+            Token::Name("d".to_string()),
+            Token::Operator(BinaryOperator::GreaterEqual),
+            Token::Number(24),
+            Token::RightParen,
+        ]);
+
+        let tokens = token_list("w!=1").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Name("w".to_string()),
+            Token::Operator(BinaryOperator::NotEqual),
+            Token::Number(1),
+        ]);
+
+        let tokens = token_list("y==2021").unwrap();
+        assert_eq!(tokens, vec![
+            Token::Name("y".to_string()),
+            Token::Operator(BinaryOperator::Equal),
+            Token::Number(2021),
+        ]);
+
+        let spans: Vec<Range<usize>> = tokenize("w >= 12 &dec").unwrap().into_iter()
+            .map(|(_, span)| span)
+            .collect();
+        assert_eq!(spans, vec![0..1, 2..4, 5..7, 8..9, 9..12]);
+
+        assert_eq!(tokenize("w=1 ; d=2").err().unwrap().span, 4..5);
+        assert!(tokenize("d=3rd").is_err());
+    }
+
+    #[test]
+    fn precedence_test() {
+        // & binds tighter than |
+        let expr = parse_expression_western("w=1 | w=2 & d=3").unwrap();
+        assert_eq!(expr, binary(BinaryOperator::Or,
+            binary(BinaryOperator::Equal, var(Variable::W), num(1)),
+            binary(BinaryOperator::And,
+                binary(BinaryOperator::Equal, var(Variable::W), num(2)),
+                binary(BinaryOperator::Equal, var(Variable::D), num(3))),
+        ));
+
+        let expr = parse_expression_western("!w=6 & d=1").unwrap();
+        assert_eq!(expr, binary(BinaryOperator::And,
+            DateExpression::Not(Box::new(
+                binary(BinaryOperator::Equal, var(Variable::W), num(6)))),
+            binary(BinaryOperator::Equal, var(Variable::D), num(1)),
+        ));
+
+        // % binds tighter than +, which binds tighter than comparison.
+        let expr = parse_expression_western("y%4+1>=-2").unwrap();
+        assert_eq!(expr, binary(BinaryOperator::GreaterEqual,
+            binary(BinaryOperator::Add,
+                binary(BinaryOperator::Modulo, var(Variable::Y), num(4)),
+                num(1)),
+            DateExpression::Negate(Box::new(num(2))),
+        ));
+
+        // + and - are left associative.
+        let expr = parse_expression_western("d-1-2").unwrap();
+        assert_eq!(expr, binary(BinaryOperator::Subtract,
+            binary(BinaryOperator::Subtract, var(Variable::D), num(1)),
+            num(2),
+        ));
+
+        let expr = parse_expression_western("m=Jan").unwrap();
+        assert_eq!(expr, binary(BinaryOperator::Equal, var(Variable::M), num(1)));
+
+        let expr = parse_expression_western("w=Fri").unwrap();
+        assert_eq!(expr, binary(BinaryOperator::Equal, var(Variable::W), num(5)));
+
+        let expr = parse_expression_western("m>=fe & w<fr").unwrap();
+        assert_eq!(expr, binary(BinaryOperator::And,
+            binary(BinaryOperator::GreaterEqual, var(Variable::M), num(2)),
+            binary(BinaryOperator::Less, var(Variable::W), num(5)),
+        ));
+        assert!(parse_expression_western("w=tu|w=t").is_err());
+
+        let expr = parse_expression("e=-2", Easter::Orthodox).unwrap();
+        assert_eq!(expr, binary(BinaryOperator::Equal,
+            var(Variable::E(Easter::Orthodox)),
+            DateExpression::Negate(Box::new(num(2))),
+        ));
+    }
+
+    #[test]
+    fn parse_errors_test() {
+        assert!(parse_expression_western("(w=1 | w=2").is_err());
+        assert!(parse_expression_western("w=1 | w=2)").is_err());
+        assert!(parse_expression_western("w=1 |").is_err());
+        assert!(parse_expression_western("x=1").is_err());
+        assert!(parse_expression_western("m=ju").is_err());
+        assert!(parse_expression_western("w=1 w=2").is_err());
+        assert!(parse_expression_western("w=1=2").is_err());
+        assert!(parse_expression_western("d>").is_err());
+    }
+
+    #[test]
+    fn parse_error_span_test() {
+        let error = |s| parse_expression_western(s).err().unwrap();
+        assert_eq!(error("(w=1 | w=2"), ParseError::new("Expected ')' at end of expression", 10..10));
+        assert_eq!(error("w=1 | w=2)"), ParseError::new("Expected operator, found )", 9..10));
+        assert_eq!(error("w=1 & x=1"), ParseError::new("Unknown variable x", 6..7));
+        assert_eq!(error("w=1 & m=ju"), ParseError::new("Ambiguous month ju: could be june or july", 8..10));
+        assert_eq!(error("w=1 &"), ParseError::new("Expected number, variable or '(' at end of expression", 5..5));
+        assert_eq!(error("w=1 & & w=2"), ParseError::new("Expected number, variable or '(', found &", 6..7));
+        assert_eq!(error("d>=99999999999999999999"), ParseError::new("Bad number 99999999999999999999", 3..23));
+        assert_eq!(error("w <= >"), ParseError::new("Expected number, variable or '(', found >", 5..6));
+    }
+}
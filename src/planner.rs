@@ -0,0 +1,257 @@
+use crate::date;
+use crate::datecalc::{DateChecker, NumberCheck, Variable};
+use chrono::{Datelike, Duration};
+use std::convert::TryFrom;
+
+// Plan for finding dates that a date checker matches without checking
+// every day. Any date that the checker matches passes the checks of year,
+// month, day of month, day of year and weekday in the plan; they come from
+// the fields of date patterns like * Dec 25, and from comparisons in
+// expressions like m=dec & d>=24. Dates that pass them are candidates,
+// which still have to be checked by the checker.
+#[derive(PartialEq, Debug)]
+pub(crate) struct Plan {
+    year: NumberCheck,
+    month: NumberCheck,
+    day: NumberCheck,
+    year_day: NumberCheck,
+    weekday: NumberCheck,
+}
+
+impl Plan {
+    pub(crate) fn new(checker: &DateChecker) -> Plan {
+        match checker {
+            DateChecker::Spec { year, month, day, weekday } => Plan {
+                year: year.clone(),
+                month: month.clone(),
+                day: day.clone(),
+                year_day: NumberCheck::Any,
+                weekday: weekday.clone(),
+            },
+            DateChecker::Expr(e) => {
+                let range = |variable: Variable| variable.range().unwrap_or((0, u32::MAX as i64));
+                let check = |variable: Variable, (first, last): (i64, i64)| {
+                    let (min, max) = range(variable);
+                    let (first, last) = (first.max(min), last.min(max));
+                    if (first, last) == (min, max) {
+                        NumberCheck::Any
+                    } else if first > last {
+                        NumberCheck::List(vec![]) // matches nothing
+                    } else {
+                        NumberCheck::Range { first: first as u32, last: last as u32, step: 1 }
+                    }
+                };
+                // Week of the month a=n is days 7n-6 to 7n of the month.
+                let (first_day, last_day) = e.bounds(Variable::D);
+                let (first_week, last_week) = e.bounds(Variable::A);
+                let (min_week, max_week) = range(Variable::A);
+                let days = (first_day.max(7 * (first_week.max(min_week) - 1) + 1),
+                            last_day.min(7 * last_week.min(max_week)));
+                Plan {
+                    year: check(Variable::Y, e.bounds(Variable::Y)),
+                    month: check(Variable::M, e.bounds(Variable::M)),
+                    day: check(Variable::D, days),
+                    year_day: check(Variable::Z, e.bounds(Variable::Z)),
+                    weekday: check(Variable::W, e.bounds(Variable::W)),
+                }
+            },
+            // Days of multi-day events follow the dates they start on, so
+            // every day is a candidate.
+            DateChecker::Span { .. } => Plan {
+                year: NumberCheck::Any,
+                month: NumberCheck::Any,
+                day: NumberCheck::Any,
+                year_day: NumberCheck::Any,
+                weekday: NumberCheck::Any,
+            },
+        }
+    }
+
+    // days_to_weekday returns the number of days from date to the first
+    // date in direction, 1 or -1, whose weekday passes the weekday check.
+    fn days_to_weekday(&self, date: &date::Date, direction: i64) -> Option<i64> {
+        let weekday = date.weekday().num_days_from_monday() as i64;
+        (0..7)
+            .map(|k| k * direction)
+            .find(|k| self.weekday.check(((weekday + k).rem_euclid(7) + 1) as u32))
+    }
+
+    // next_date returns the first candidate from date on, or None if there
+    // is none until limit.
+    pub(crate) fn next_date(&self, date: date::Date, limit: &date::Date) -> Option<date::Date> {
+        let mut date = date;
+        while date <= *limit {
+            let (year, month, day) = (date.year(), date.month(), date.day());
+            // The year check is for years from 0 on, like in date patterns.
+            if let Ok(y) = u32::try_from(year) {
+                match self.year.next_value(y) {
+                    None => return None,
+                    Some(next) if next != y => {
+                        date = date::new_date_opt(i32::try_from(next).ok()?, 1, 1)?;
+                        continue;
+                    },
+                    _ => {},
+                }
+            }
+            match self.month.next_value(month).filter(|m| *m <= 12) {
+                None => {
+                    date = date::new_date_opt(year.checked_add(1)?, 1, 1)?;
+                    continue;
+                },
+                Some(next) if next != month => {
+                    date = date::new_date_opt(year, next, 1)?;
+                    continue;
+                },
+                _ => {},
+            }
+            match self.day.next_value(day).filter(|d| *d <= date::days_in_month(year, month)) {
+                None => {
+                    date = date::new_date_opt(year, month, date::days_in_month(year, month))?
+                        .succ_opt()?;
+                    continue;
+                },
+                Some(next) if next != day => {
+                    date = date::new_date_opt(year, month, next)?;
+                    continue;
+                },
+                _ => {},
+            }
+            let ordinal = date.ordinal();
+            match self.year_day.next_value(ordinal) {
+                Some(next) if next != ordinal => {
+                    date = date::Date::from_yo_opt(year, next)
+                        .or_else(|| date::new_date_opt(year.checked_add(1)?, 1, 1))?;
+                    continue;
+                },
+                None => {
+                    date = date::new_date_opt(year.checked_add(1)?, 1, 1)?;
+                    continue;
+                },
+                _ => {},
+            }
+            if let Some(days) = self.days_to_weekday(&date, 1) {
+                if days > 0 {
+                    date = date.checked_add_signed(Duration::days(days))?;
+                    continue;
+                }
+            }
+            return Some(date);
+        }
+        None
+    }
+
+    // previous_date is like next_date, but returns the last candidate from
+    // date back, or None if there is none back to limit.
+    pub(crate) fn previous_date(&self, date: date::Date, limit: &date::Date) ->
+        Option<date::Date>
+    {
+        let mut date = date;
+        while date >= *limit {
+            let (year, month, day) = (date.year(), date.month(), date.day());
+            if let Ok(y) = u32::try_from(year) {
+                match self.year.previous_value(y) {
+                    None => return None,
+                    Some(previous) if previous != y => {
+                        date = date::new_date_opt(i32::try_from(previous).ok()?, 12, 31)?;
+                        continue;
+                    },
+                    _ => {},
+                }
+            }
+            match self.month.previous_value(month) {
+                None => {
+                    date = date::new_date_opt(year, 1, 1)?.pred_opt()?;
+                    continue;
+                },
+                Some(previous) if previous != month => {
+                    let last_day = date::days_in_month(year, previous);
+                    date = date::new_date_opt(year, previous, last_day)?;
+                    continue;
+                },
+                _ => {},
+            }
+            match self.day.previous_value(day) {
+                None => {
+                    date = date::new_date_opt(year, month, 1)?.pred_opt()?;
+                    continue;
+                },
+                Some(previous) if previous != day => {
+                    date = date::new_date_opt(year, month, previous)?;
+                    continue;
+                },
+                _ => {},
+            }
+            let ordinal = date.ordinal();
+            match self.year_day.previous_value(ordinal) {
+                None => {
+                    date = date::new_date_opt(year, 1, 1)?.pred_opt()?;
+                    continue;
+                },
+                Some(previous) if previous != ordinal => {
+                    date = date::Date::from_yo_opt(year, previous)?;
+                    continue;
+                },
+                _ => {},
+            }
+            if let Some(days) = self.days_to_weekday(&date, -1) {
+                if days < 0 {
+                    date = date.checked_add_signed(Duration::days(days))?;
+                    continue;
+                }
+            }
+            return Some(date);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::new_date;
+
+    fn new_plan(expr: &str) -> Plan {
+        Plan::new(&DateChecker::new(expr).unwrap())
+    }
+
+    #[test]
+    fn plan_new_test() {
+        let plan = new_plan("m=dec & (d=24 | d=31) & y>=2020 & 300<z");
+        assert_eq!(plan.year, NumberCheck::Range { first: 2020, last: u32::MAX, step: 1 });
+        assert_eq!(plan.month, NumberCheck::Range { first: 12, last: 12, step: 1 });
+        assert_eq!(plan.day, NumberCheck::Range { first: 24, last: 31, step: 1 });
+        assert_eq!(plan.year_day, NumberCheck::Range { first: 301, last: 366, step: 1 });
+        assert_eq!(plan.weekday, NumberCheck::Any);
+
+        assert_eq!(new_plan("!(m=dec)").month, NumberCheck::Any);
+        assert_eq!(new_plan("w=mon & a=2").day, NumberCheck::Range { first: 8, last: 14, step: 1 });
+        assert_eq!(new_plan("a>=4 & d<30").day, NumberCheck::Range { first: 22, last: 29, step: 1 });
+        assert_eq!(new_plan("m=1 & m=2").month, NumberCheck::List(vec![]));
+    }
+
+    #[test]
+    fn next_date_test() {
+        let limit = new_date(2100, 12, 31);
+        let next = |expr, date| new_plan(expr).next_date(date, &limit);
+        assert_eq!(next("* Dec 25", new_date(2024, 12, 26)), Some(new_date(2025, 12, 25)));
+        assert_eq!(next("* Dec 25", new_date(2024, 12, 25)), Some(new_date(2024, 12, 25)));
+        assert_eq!(next("* feb 29", new_date(2024, 3, 1)), Some(new_date(2028, 2, 29)));
+        assert_eq!(next("2030-2040/5 jan 1", new_date(2024, 3, 1)), Some(new_date(2030, 1, 1)));
+        assert_eq!(next("* * fri", new_date(2024, 6, 1)), Some(new_date(2024, 6, 7)));
+        assert_eq!(next("z=60", new_date(2024, 3, 1)), Some(new_date(2025, 3, 1)));
+        assert_eq!(next("z=366", new_date(2025, 1, 1)), Some(new_date(2028, 12, 31)));
+        assert_eq!(next("y=1999", new_date(2024, 1, 1)), None);
+        assert_eq!(next("y=2200", new_date(2024, 1, 1)), None);
+    }
+
+    #[test]
+    fn previous_date_test() {
+        let limit = new_date(1900, 1, 1);
+        let previous = |expr, date| new_plan(expr).previous_date(date, &limit);
+        assert_eq!(previous("* Dec 25", new_date(2024, 12, 24)), Some(new_date(2023, 12, 25)));
+        assert_eq!(previous("* * 31", new_date(2024, 5, 30)), Some(new_date(2024, 3, 31)));
+        assert_eq!(previous("m<=feb & d>=28", new_date(2024, 6, 1)), Some(new_date(2024, 2, 29)));
+        assert_eq!(previous("z=366", new_date(2024, 12, 30)), Some(new_date(2020, 12, 31)));
+        assert_eq!(previous("y=2200", new_date(2024, 1, 1)), None);
+    }
+}
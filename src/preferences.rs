@@ -1,20 +1,17 @@
 use regex::Regex;
 use std::collections::HashMap;
+use std::path::Path;
 
 pub fn parse_line(line: &str) -> Result<(String, String), String> {
-    let re = Regex::new(r"\s*(\S.*)\s*=\s*(\S.*) *$").unwrap();
+    let re = Regex::new(r"\s*(\S.*?)\s*=\s*(\S.*) *$").unwrap();
     let cap = re.captures(line);
     match cap {
-        None => return Err("mismatch".to_string()),
-        Some(captures) => {
-            if captures.len() == 3 {
-                let key = String::from(captures[1].trim());
-                let value = String::from(captures[2].trim());
-                return Ok((key, value));
-            } else {
-                return Err("mismatch".to_string());
-            }
+        Some(captures) if captures.len() == 3 => {
+            let key = String::from(captures[1].trim());
+            let value = String::from(captures[2].trim());
+            Ok((key, value))
         },
+        _ => Err("mismatch".to_string()),
     }
 }
 
@@ -28,7 +25,40 @@ pub fn parse_lines<'a, It: Iterator<Item=&'a str>>(lines: It) ->
             hm.insert(key, value);
         }
     }
-    return hm;
+    hm
+}
+
+// parse_calendar parses a calendar of the --calendar option or of the
+// calendars preference: the path of its file, optionally after a label, like
+// work=/home/alice/work.cal. It returns the label and the path. An existing
+// file whose name looks like a label and a path, like a=b.cal, is a path.
+pub fn parse_calendar(s: &str) -> (Option<String>, String) {
+    let re = Regex::new(r"^\s*([A-Za-z0-9_-]+)\s*=\s*(\S.*?)\s*$").unwrap();
+    match re.captures(s) {
+        Some(captures) if !Path::new(s.trim()).exists() => {
+            (Some(captures[1].to_string()), captures[2].to_string())
+        },
+        _ => (None, s.trim().to_string()),
+    }
+}
+
+// parse_calendars parses the calendars preference, a list of calendars
+// separated by commas, like
+// work=/home/alice/work.cal, /home/alice/.when-rs/calendar.
+pub fn parse_calendars(s: &str) -> Vec<(Option<String>, String)> {
+    s.split(',')
+        .filter(|calendar| !calendar.trim().is_empty())
+        .map(parse_calendar)
+        .collect()
+}
+
+// parse_labels parses values of the --only or --exclude option, each a
+// label or a list of labels separated by commas, like work,home.
+pub fn parse_labels<'a, It: Iterator<Item=&'a str>>(values: It) -> Vec<&'a str> {
+    values.flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|label| !label.is_empty())
+        .collect()
 }
 
 #[cfg(test)]
@@ -53,18 +83,45 @@ mod tests {
 
     #[test]
     fn can_parse_lines() {
-        let lines = vec![
+        let lines = [
             " a = b",
             "c=d",
             "nothing",
-            "e  =   f"
+            "e  =   f",
+            "calendars = work=/home/alice/work.cal",
         ];
 
-        let hm = parse_lines(lines.iter().map(|s| *s));
+        let hm = parse_lines(lines.iter().copied());
         eprintln!("hm is {:?}", hm);
-        assert_eq!(hm.len(), 3);
+        assert_eq!(hm.len(), 4);
         assert_eq!(hm.get("a"), Some(&String::from("b")));
         assert_eq!(hm.get("c"), Some(&String::from("d")));
         assert_eq!(hm.get("e"), Some(&String::from("f")));
+        assert_eq!(hm.get("calendars"), Some(&String::from("work=/home/alice/work.cal")));
+    }
+
+    #[test]
+    fn can_parse_calendars() {
+        assert_eq!(parse_calendar(" work = /home/alice/work.cal "),
+                   (Some("work".to_string()), "/home/alice/work.cal".to_string()));
+        assert_eq!(parse_calendar("/home/alice/a=b.cal"), (None, "/home/alice/a=b.cal".to_string()));
+        assert_eq!(parse_calendars("work=/home/alice/work.cal, /home/alice/.when-rs/calendar,"), vec![
+            (Some("work".to_string()), "/home/alice/work.cal".to_string()),
+            (None, "/home/alice/.when-rs/calendar".to_string()),
+        ]);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        let path = dir.join("work=2024.cal").display().to_string();
+        assert_eq!(parse_calendar(&path).0, None);
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(parse_calendar(&format!(" {} ", path)), (None, path.clone()));
+    }
+
+    #[test]
+    fn can_parse_labels() {
+        assert_eq!(parse_labels(["work, home", "school"].iter().copied()), vec!["work", "home", "school"]);
+        assert_eq!(parse_labels(["work,", " , "].iter().copied()), vec!["work"]);
+        assert!(parse_labels(std::iter::empty()).is_empty());
     }
 }
@@ -0,0 +1,61 @@
+use crate::error::Error;
+use regex::{Regex, RegexBuilder};
+
+// Filter of calendar items by their descriptions, like when-rs --search
+// or --grep.
+#[derive(Debug, Clone)]
+pub struct Search {
+    regex: Regex,
+    invert: bool,
+}
+
+impl Search {
+    // substring returns search for descriptions that contain text.
+    pub fn substring(text: &str, ignore_case: bool, invert: bool) -> Search {
+        Search::regex(&regex::escape(text), ignore_case, invert).unwrap()
+    }
+
+    // regex returns search for descriptions that match regular expression
+    // pattern.
+    pub fn regex(pattern: &str, ignore_case: bool, invert: bool) -> Result<Search, Error> {
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|error| Error::Search(error.to_string()))?;
+        Ok(Search { regex, invert })
+    }
+
+    // matches returns true if the item with the description passes the
+    // filter, i.e. if it matches, or if it doesn't when search is inverted.
+    pub fn matches(&self, description: &str) -> bool {
+        self.regex.is_match(description) != self.invert
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substring_test() {
+        let search = Search::substring("Alice's", false, false);
+        assert!(search.matches(" Alice's birthday"));
+        assert!(!search.matches(" alice's birthday"));
+        assert!(Search::substring("alice's", true, false).matches(" Alice's birthday"));
+        assert!(Search::substring("a.c", false, false).matches("a.c"));
+        assert!(!Search::substring("a.c", false, false).matches("abc"));
+        assert!(!Search::substring("alice", true, true).matches(" Alice's birthday"));
+        assert!(Search::substring("alice", true, true).matches(" Bob's birthday"));
+    }
+
+    #[test]
+    fn regex_test() {
+        let search = Search::regex(r"^\s*(Alice|Bob)\b", false, false).unwrap();
+        assert!(search.matches(" Bob's birthday"));
+        assert!(!search.matches(" Call Bob"));
+        assert!(Search::regex("bob", true, false).unwrap().matches(" Call Bob"));
+
+        let error = Search::regex("(Alice", false, false).err().unwrap();
+        assert_eq!(error.exit_code(), 2);
+    }
+}
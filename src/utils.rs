@@ -1,17 +1,67 @@
+use crate::date::TimeRange;
+use crate::datecalc::DateChecker;
+use crate::easter::Easter;
+use crate::parser::ParseError;
 use regex::Regex;
+use std::sync::OnceLock;
 
+// parse_calendar_line splits calendar line into date pattern and description.
+// Since date patterns can contain commas, like * jun,aug 15, the pattern ends
+// at the first comma followed by a space that has a valid pattern before it,
+// or at the first comma if there is no such comma.
 pub fn parse_calendar_line(line: &str) -> Option<(String, String)> {
-    let re = Regex::new(r"([^,]+),(.*)").unwrap();
-    match re.captures(line) {
-        None => None,
-        Some(captures) => {
-            Some( (
-                       captures.get(1).unwrap().as_str().to_string(),
-                       captures.get(2).unwrap().as_str().to_string(),
-                   )
-                )
-        },
+    static COMMA: OnceLock<Regex> = OnceLock::new();
+    let re = COMMA.get_or_init(|| Regex::new(r",(\s|$)").unwrap());
+    for m in re.find_iter(line) {
+        let expr = &line[..m.start()];
+        if !expr.is_empty() && DateChecker::new(expr).is_ok() {
+            return Some((expr.to_string(), line[m.start() + 1..].to_string()));
+        }
     }
+
+    static FIRST_COMMA: OnceLock<Regex> = OnceLock::new();
+    let re = FIRST_COMMA.get_or_init(|| Regex::new(r"([^,]+),(.*)").unwrap());
+    re.captures(line).map(|captures| {
+        (
+            captures.get(1).unwrap().as_str().to_string(),
+            captures.get(2).unwrap().as_str().to_string(),
+        )
+    })
+}
+
+// parse_time splits the time, like 09:30 or 09:30-10:00, from the start of
+// the description of a calendar item. The time is separated from the rest
+// of the description by a comma, as in "w=1, 09:30-10:00, Standup".
+pub fn parse_time(descr: &str) -> (Option<TimeRange>, String) {
+    if let Some((time, rest)) = descr.split_once(',') {
+        if let Some(time) = TimeRange::parse(time) {
+            return (Some(time), rest.to_string());
+        }
+    }
+    (None, descr.to_string())
+}
+
+// parse_calendar_entry parses a line of the calendar file into the date
+// checker, the time and the description, without the spaces around it. It
+// returns None for comments and empty lines.
+pub fn parse_calendar_entry(line: &str, easter: Easter) ->
+    Result<Option<(DateChecker, Option<TimeRange>, String)>, ParseError>
+{
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return Ok(None);
+    }
+    let (expr, descr) = match parse_calendar_line(line) {
+        Some(parts) => parts,
+        None => {
+            let message = "Expected ',' between date pattern and description";
+            let end = line.trim_end().len();
+            return Err(ParseError::new(message, end..end));
+        }
+    };
+    let checker = DateChecker::with_easter(&expr, easter)?;
+    let (time, descr) = parse_time(&descr);
+    Ok(Some((checker, time, descr.trim().to_string())))
 }
 
 #[cfg(test)]
@@ -33,6 +83,52 @@ mod tests {
         assert_eq!(r.1, "This, my friend, is the description");
     }
 
+    #[test]
+    fn pattern_with_commas_test() {
+        let r = parse_calendar_line("* jun,aug 15, Mid-month review, again").unwrap();
+        assert_eq!(r.0, "* jun,aug 15");
+        assert_eq!(r.1, " Mid-month review, again");
+
+        let r = parse_calendar_line("* * 1,15,").unwrap();
+        assert_eq!(r.0, "* * 1,15");
+        assert_eq!(r.1, "");
+    }
+
+    #[test]
+    fn parse_time_test() {
+        let (time, descr) = parse_time(" 09:30-10:00, Standup");
+        assert_eq!(time.unwrap().to_string(), "09:30-10:00");
+        assert_eq!(descr, " Standup");
+
+        let (time, descr) = parse_time(" 9:30,Standup, again");
+        assert_eq!(time.unwrap().to_string(), "09:30");
+        assert_eq!(descr, "Standup, again");
+
+        let (time, descr) = parse_time(" Standup, at 9:30");
+        assert!(time.is_none());
+        assert_eq!(descr, " Standup, at 9:30");
+
+        let (time, descr) = parse_time(" 10:00 Standup");
+        assert!(time.is_none());
+        assert_eq!(descr, " 10:00 Standup");
+    }
+
+    #[test]
+    fn parse_calendar_entry_test() {
+        assert!(parse_calendar_entry("# comment, really", Easter::Western).unwrap().is_none());
+        assert!(parse_calendar_entry("   ", Easter::Western).unwrap().is_none());
+
+        let (_, time, descr) = parse_calendar_entry("w=1, 09:30, Standup", Easter::Western)
+            .unwrap().unwrap();
+        assert_eq!(time.unwrap().to_string(), "09:30");
+        assert_eq!(descr, "Standup");
+
+        let error = parse_calendar_entry("* Jly 4, Independence Day", Easter::Western);
+        assert_eq!(error.err().unwrap(), ParseError::new("Bad month", 2..5));
+        let error = parse_calendar_entry("no comma ", Easter::Western);
+        assert_eq!(error.err().unwrap().span, 8..8);
+    }
+
     #[test]
     fn negative_test() {
         let r = parse_calendar_line("abc def");